@@ -11,6 +11,10 @@ pub static RSW_DIR: &str = ".rsw";
 pub static RSW_CRATES: &str = "rsw.crates";
 pub static RSW_INFO: &str = "rsw.info";
 pub static RSW_ERR: &str = "rsw.err";
+pub static RSW_DIAGNOSTICS: &str = "rsw.diagnostics.json";
+pub static RSW_FINGERPRINT: &str = "fingerprint";
+pub static RSW_SIZE: &str = "size";
+pub static RSW_MANIFEST: &str = "rsw.manifest.json";
 
 /// rust crate config
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,19 +43,203 @@ pub struct CrateConfig {
     pub watch: Option<WatchOptions>,
     #[serde(default = "default_build")]
     pub build: Option<BuildOptions>,
-    /// target: bundler | nodejs | web | no-modules
+    /// target: bundler | nodejs | web | no-modules,
+    /// or a list of targets, e.g. `["web", "nodejs"]`
     ///
     /// <https://rustwasm.github.io/wasm-pack/book/commands/build.html#target>
+    ///
+    /// With several targets, each one is built into its own directory under `out-dir`,
+    /// e.g. `pkg/web`, `pkg/node`.
     #[serde(default = "default_target")]
-    pub target: Option<String>,
+    pub target: Option<Targets>,
+    /// Combine the outputs of all targets into one npm package in `out-dir`,
+    /// with an `exports` map for Node.js (ESM and CommonJS), browsers and bundlers.
+    /// default is `false`
+    #[serde(default = "default_false")]
+    pub combined: Option<bool>,
+    /// Build for shared memory and threads, e.g. with `wasm-bindgen-rayon`:
+    /// nightly Rust, the `atomics` and `bulk-memory` target features and `-Z build-std`.
+    /// `rsw serve` sends the cross-origin isolation headers these crates need.
+    /// default is `false`
+    #[serde(default = "default_false")]
+    pub threads: Option<bool>,
+    /// `rsw build` fails when the `.wasm` grows past this size,
+    /// e.g. `"200KB"`, `"1.5MB"` or a number of bytes
+    pub size_budget: Option<SizeBudget>,
+    /// kill `wasm-pack build` when the crate takes longer than this, in seconds
+    pub timeout: Option<u64>,
     /// scope: npm organization
     ///
     /// <https://rustwasm.github.io/wasm-pack/book/commands/build.html#scope>
     pub scope: Option<String>,
+    /// `wasm-pack build` and cargo arguments, used by both `rsw watch` and `rsw build`
+    #[serde(flatten)]
+    pub args: ArgsOptions,
+    /// `pre-build`, `post-build` and `on-failure` commands of the crate,
+    /// run after the global ones
+    #[serde(flatten)]
+    pub hooks: HooksOptions,
     // TODO
     // pub mode: Option<String>,
 }
 
+/// `target = "web"` or `target = ["web", "nodejs"]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Targets {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Targets {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            Targets::One(target) => vec![target.clone()],
+            Targets::Many(targets) => targets.clone(),
+        }
+    }
+}
+
+impl CrateConfig {
+    /// The targets and their output directories, relative to the crate root.
+    /// A single target is built into `out-dir`,
+    /// several targets or a `combined` package are built into `<out-dir>/<target>`.
+    pub fn outputs(&self) -> Vec<(String, String)> {
+        let out_dir = self.out_dir.as_deref().unwrap_or("pkg");
+        let targets = match &self.target {
+            Some(targets) if !targets.to_vec().is_empty() => targets.to_vec(),
+            _ => vec!["web".into()],
+        };
+        if targets.len() == 1 && !self.is_combined() {
+            return vec![(targets[0].clone(), out_dir.into())];
+        }
+        targets
+            .into_iter()
+            .map(|target| {
+                let dir = match target.as_str() {
+                    "nodejs" => "node",
+                    t => t,
+                };
+                let dir = format!("{}/{}", out_dir, dir);
+                (target, dir)
+            })
+            .collect()
+    }
+
+    /// The output directory used by `link`, relative to the crate root
+    pub fn link_dir(&self) -> String {
+        if self.is_combined() {
+            return self.out_dir.clone().unwrap_or_else(|| "pkg".into());
+        }
+        self.outputs().remove(0).1
+    }
+
+    pub fn is_combined(&self) -> bool {
+        self.combined.unwrap_or(false)
+    }
+
+    pub fn is_threads(&self) -> bool {
+        self.threads.unwrap_or(false)
+    }
+}
+
+/// `size-budget = "200KB"` or `size-budget = 204800`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SizeBudget {
+    Bytes(u64),
+    Text(String),
+}
+
+/// `wasm-pack build` and cargo arguments
+///
+/// <https://rustwasm.github.io/wasm-pack/book/commands/build.html>
+///
+/// When set in `[crates.watch]` or `[crates.build]`, options override those of the crate
+/// and lists are appended to those of the crate.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ArgsOptions {
+    /// cargo `--features`
+    pub features: Option<Vec<String>>,
+    /// cargo `--no-default-features`
+    pub no_default_features: Option<bool>,
+    /// `--out-name`, sets the prefix for output file names
+    pub out_name: Option<String>,
+    /// `--no-typescript`, don't generate the `*.d.ts` file
+    pub no_typescript: Option<bool>,
+    /// `--weak-refs`, enable usage of the JS weak references proposal
+    pub weak_refs: Option<bool>,
+    /// `--reference-types`, enable usage of WebAssembly reference types
+    pub reference_types: Option<bool>,
+    /// `--no-pack`, don't generate `package.json` and `README.md`
+    pub no_pack: Option<bool>,
+    /// extra `wasm-pack build` arguments, e.g. `["--mode", "no-install"]`
+    pub extra_args: Option<Vec<String>>,
+    /// extra cargo arguments, passed after `--`, e.g. `["--locked"]`
+    pub cargo_args: Option<Vec<String>>,
+}
+
+impl ArgsOptions {
+    /// `self` with the options of `mode` (`[crates.watch]` or `[crates.build]`) applied
+    pub fn merge(&self, mode: &ArgsOptions) -> ArgsOptions {
+        fn list(a: &Option<Vec<String>>, b: &Option<Vec<String>>) -> Option<Vec<String>> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some([&a[..], &b[..]].concat()),
+                _ => a.clone().or_else(|| b.clone()),
+            }
+        }
+
+        ArgsOptions {
+            features: list(&self.features, &mode.features),
+            no_default_features: mode.no_default_features.or(self.no_default_features),
+            out_name: mode.out_name.clone().or_else(|| self.out_name.clone()),
+            no_typescript: mode.no_typescript.or(self.no_typescript),
+            weak_refs: mode.weak_refs.or(self.weak_refs),
+            reference_types: mode.reference_types.or(self.reference_types),
+            no_pack: mode.no_pack.or(self.no_pack),
+            extra_args: list(&self.extra_args, &mode.extra_args),
+            cargo_args: list(&self.cargo_args, &mode.cargo_args),
+        }
+    }
+
+    /// `wasm-pack build` arguments
+    pub fn wasm_pack_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(out_name) = &self.out_name {
+            args.push("--out-name".into());
+            args.push(out_name.into());
+        }
+        let flags = [
+            (self.no_typescript, "--no-typescript"),
+            (self.weak_refs, "--weak-refs"),
+            (self.reference_types, "--reference-types"),
+            (self.no_pack, "--no-pack"),
+        ];
+        for (enabled, flag) in flags {
+            if enabled.unwrap_or(false) {
+                args.push(flag.into());
+            }
+        }
+        args.extend(self.extra_args.iter().flatten().cloned());
+        args
+    }
+
+    /// cargo arguments, passed to `wasm-pack build` after `--`
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(features) = self.features.as_ref().filter(|f| !f.is_empty()) {
+            args.push("--features".into());
+            args.push(features.join(","));
+        }
+        if self.no_default_features.unwrap_or(false) {
+            args.push("--no-default-features".into());
+        }
+        args.extend(self.cargo_args.iter().flatten().cloned());
+        args
+    }
+}
+
 /// `rsw watch` - watch config
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -71,6 +259,30 @@ pub struct WatchOptions {
     /// which helps when investigating performance issues in a profiler.
     #[serde(default = "default_dev")]
     pub profile: Option<String>,
+    /// run `wasm-opt` after the build instead of `wasm-pack`
+    pub wasm_opt: Option<WasmOptOptions>,
+    /// what to do when files change while the crate is building, default is `abort`
+    pub strategy: Option<WatchStrategy>,
+    /// extra files to watch, globs relative to the crate, e.g. `["assets/**/*.txt"]`
+    pub paths: Option<Vec<String>>,
+    /// files of the crate that do not trigger a rebuild, in `.watchignore` (gitignore) syntax
+    pub ignore: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub args: ArgsOptions,
+}
+
+/// `rsw watch` - rebuild strategy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WatchStrategy {
+    /// kill the running build and start again
+    #[default]
+    Abort,
+    /// finish the running build, then build once for each change made meanwhile, in order
+    Queue,
+    /// finish the running build, then build once for all changes made meanwhile,
+    /// only the last change is kept
+    Coalesce,
 }
 
 /// `rsw build` - build config
@@ -89,6 +301,47 @@ pub struct BuildOptions {
     /// which helps when investigating performance issues in a profiler.
     #[serde(default = "default_release")]
     pub profile: Option<String>,
+    /// run `wasm-opt` after the build instead of `wasm-pack`
+    pub wasm_opt: Option<WasmOptOptions>,
+    #[serde(flatten)]
+    pub args: ArgsOptions,
+}
+
+/// `[crates.watch.wasm-opt]` | `[crates.build.wasm-opt]`
+///
+/// <https://github.com/WebAssembly/binaryen>
+///
+/// When configured, `wasm-pack build --no-opt` skips its own wasm-opt step (wasm-pack >= 0.12),
+/// and rsw runs `wasm-opt` on the generated `.wasm` files after the build.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WasmOptOptions {
+    /// run wasm-opt: `true` | `false`, default is `true`
+    #[serde(default = "default_true")]
+    pub enabled: Option<bool>,
+    /// optimization level: `O` | `O1` | `O2` | `O3` | `O4` | `Os` | `Oz`, default is `O`
+    #[serde(default = "default_opt_level")]
+    pub level: Option<String>,
+    /// extra wasm-opt passes and arguments, e.g. `["--strip-debug", "--enable-simd"]`
+    pub passes: Option<Vec<String>>,
+}
+
+impl WasmOptOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// `wasm-opt` arguments, without the input and output files
+    pub fn args(&self) -> Vec<String> {
+        let level = self.level.as_deref().unwrap_or("O").trim_start_matches('-');
+        let level = match level.starts_with('O') {
+            true => format!("-{}", level),
+            false => format!("-O{}", level),
+        };
+        let mut args = vec![level];
+        args.extend(self.passes.iter().flatten().cloned());
+        args
+    }
 }
 
 /// `rsw new` - new config
@@ -109,6 +362,35 @@ pub struct NewOptions {
     pub dir: Option<String>,
 }
 
+/// `rsw watch` - file watcher config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WatcherOptions {
+    /// Poll the files for changes instead of using the native file system events,
+    /// for Docker volumes and network file systems. default is `false`
+    #[serde(default = "default_false")]
+    pub poll: Option<bool>,
+    /// How often to poll, in milliseconds. default is `1000`
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: Option<u64>,
+}
+
+/// Commands run around the build of a crate, with `sh -c` (`cmd /C` on Windows)
+/// in the project root.
+///
+/// Hooks get the `RSW_CRATE_NAME`, `RSW_CRATE_ROOT`, `RSW_OUT_DIR`, `RSW_PROFILE`
+/// and `RSW_MODE` environment variables.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HooksOptions {
+    /// before `wasm-pack build`, a failing command fails the build
+    pub pre_build: Option<String>,
+    /// after a successful build, a failing command fails the build
+    pub post_build: Option<String>,
+    /// after a failed build
+    pub on_failure: Option<String>,
+}
+
 /// rsw config
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RswConfig {
@@ -120,8 +402,17 @@ pub struct RswConfig {
     pub cli: Option<String>,
     /// In `watch` mode, the time interval for `wasm-pack build`, in milliseconds.
     pub interval: Option<u64>,
+    /// The maximum number of crates to build in parallel, default is `1`
+    #[serde(default = "default_jobs")]
+    pub jobs: Option<usize>,
     #[serde(default = "default_new")]
     pub new: Option<NewOptions>,
+    /// `[watch]` - the file watcher of `rsw watch`
+    #[serde(default = "default_watcher")]
+    pub watch: Option<WatcherOptions>,
+    /// hooks run for every crate
+    #[serde(flatten)]
+    pub hooks: HooksOptions,
     /// rust crates
     #[serde(default)]
     pub crates: Vec<CrateConfig>,
@@ -133,8 +424,11 @@ impl Default for RswConfig {
             name: Some("rsw".into()),
             version: Some("0.0.0".into()),
             interval: Some(50),
+            jobs: default_jobs(),
             cli: Some("npm".into()),
             new: default_new(),
+            watch: default_watcher(),
+            hooks: HooksOptions::default(),
             crates: vec![],
         }
     }
@@ -142,18 +436,24 @@ impl Default for RswConfig {
 
 impl RswConfig {
     pub fn new() -> Result<RswConfig, Error> {
-        let rsw_file = env::current_dir().unwrap().join(RSW_FILE);
-        let rsw_content = fs::read_to_string(rsw_file).unwrap_or_else(|e| {
-            print(RswErr::Config(e));
-            process::exit(1);
-        });
-        let rsw_toml_parse = toml::from_str(&rsw_content).unwrap_or_else(|e| {
-            print(RswErr::ParseToml(e));
+        let rsw_toml_parse = RswConfig::load().unwrap_or_else(|e| {
+            print(e);
             process::exit(1);
         });
 
         Ok(rsw_toml_parse)
     }
+
+    /// Read `rsw.toml` in the project root
+    pub fn load() -> Result<RswConfig, RswErr> {
+        let rsw_file = env::current_dir().unwrap().join(RSW_FILE);
+        let rsw_content = fs::read_to_string(rsw_file).map_err(RswErr::Config)?;
+        toml::from_str(&rsw_content).map_err(RswErr::ParseToml)
+    }
+}
+
+fn default_jobs() -> Option<usize> {
+    Some(1)
 }
 
 fn default_root() -> Option<String> {
@@ -172,8 +472,12 @@ fn default_dev() -> Option<String> {
     Some("dev".into())
 }
 
-fn default_target() -> Option<String> {
-    Some("web".into())
+fn default_target() -> Option<Targets> {
+    Some(Targets::One("web".into()))
+}
+
+fn default_opt_level() -> Option<String> {
+    Some("O".into())
 }
 
 fn default_true() -> Option<bool> {
@@ -199,10 +503,26 @@ fn default_new() -> Option<NewOptions> {
     })
 }
 
+fn default_watcher() -> Option<WatcherOptions> {
+    Some(WatcherOptions {
+        poll: default_false(),
+        poll_interval: default_poll_interval(),
+    })
+}
+
+fn default_poll_interval() -> Option<u64> {
+    Some(1000)
+}
+
 fn default_watch() -> Option<WatchOptions> {
     Some(WatchOptions {
         run: default_true(),
         profile: default_dev(),
+        wasm_opt: None,
+        strategy: None,
+        paths: None,
+        ignore: None,
+        args: ArgsOptions::default(),
     })
 }
 
@@ -210,5 +530,165 @@ fn default_build() -> Option<BuildOptions> {
     Some(BuildOptions {
         run: default_true(),
         profile: default_release(),
+        wasm_opt: None,
+        args: ArgsOptions::default(),
     })
 }
+
+#[cfg(test)]
+mod crate_outputs_tests {
+    use super::*;
+
+    fn crate_config(content: &str) -> CrateConfig {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn one_target() {
+        let config = crate_config("name = \"foo\"\nout-dir = \"dist\"");
+        assert_eq!(config.outputs(), vec![("web".into(), "dist".into())]);
+    }
+
+    #[test]
+    fn many_targets() {
+        let config = crate_config("name = \"foo\"\ntarget = [\"web\", \"nodejs\"]");
+        assert_eq!(
+            config.outputs(),
+            vec![
+                ("web".into(), "pkg/web".into()),
+                ("nodejs".into(), "pkg/node".into())
+            ]
+        );
+        assert_eq!(config.link_dir(), "pkg/web");
+    }
+
+    #[test]
+    fn combined_targets() {
+        let config = crate_config("name = \"foo\"\ntarget = \"web\"\ncombined = true");
+        assert_eq!(config.outputs(), vec![("web".into(), "pkg/web".into())]);
+        assert_eq!(config.link_dir(), "pkg");
+    }
+}
+
+#[cfg(test)]
+mod args_options_tests {
+    use super::*;
+
+    #[test]
+    fn merge_mode_options() {
+        let crate_args = ArgsOptions {
+            features: Some(vec!["a".into()]),
+            no_typescript: Some(true),
+            out_name: Some("foo".into()),
+            ..Default::default()
+        };
+        let mode_args = ArgsOptions {
+            features: Some(vec!["b".into()]),
+            no_typescript: Some(false),
+            ..Default::default()
+        };
+        let args = crate_args.merge(&mode_args);
+        assert_eq!(args.features, Some(vec!["a".into(), "b".into()]));
+        assert_eq!(args.no_typescript, Some(false));
+        assert_eq!(args.out_name, Some("foo".into()));
+    }
+
+    #[test]
+    fn wasm_pack_and_cargo_args() {
+        let args: ArgsOptions = toml::from_str(
+            r#"
+            features = ["a", "b"]
+            no-default-features = true
+            out-name = "foo"
+            weak-refs = true
+            extra-args = ["--mode", "no-install"]
+            cargo-args = ["--locked"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            args.wasm_pack_args(),
+            vec!["--out-name", "foo", "--weak-refs", "--mode", "no-install"]
+        );
+        assert_eq!(
+            args.cargo_args(),
+            vec!["--features", "a,b", "--no-default-features", "--locked"]
+        );
+    }
+}
+
+#[cfg(test)]
+mod wasm_opt_tests {
+    use super::*;
+
+    #[test]
+    fn wasm_opt_args() {
+        let config: BuildOptions = toml::from_str(
+            r#"
+            [wasm-opt]
+            level = "Oz"
+            passes = ["--strip-debug"]
+            "#,
+        )
+        .unwrap();
+        let wasm_opt = config.wasm_opt.unwrap();
+        assert!(wasm_opt.is_enabled());
+        assert_eq!(wasm_opt.args(), vec!["-Oz", "--strip-debug"]);
+    }
+
+    #[test]
+    fn wasm_opt_level() {
+        let wasm_opt: WasmOptOptions = toml::from_str("level = \"-O3\"").unwrap();
+        assert_eq!(wasm_opt.args(), vec!["-O3"]);
+        let wasm_opt: WasmOptOptions = toml::from_str("level = \"4\"").unwrap();
+        assert_eq!(wasm_opt.args(), vec!["-O4"]);
+        let wasm_opt: WasmOptOptions = toml::from_str("").unwrap();
+        assert_eq!(wasm_opt.args(), vec!["-O"]);
+    }
+}
+
+#[cfg(test)]
+mod hooks_options_tests {
+    use super::*;
+
+    #[test]
+    fn global_and_crate_hooks() {
+        let config: RswConfig = toml::from_str(
+            r#"
+            pre-build = "node gen.js"
+            on-failure = "echo failed"
+
+            [[crates]]
+            name = "foo"
+            features = ["a"]
+            post-build = "cp -r pkg dist"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.hooks.pre_build.as_deref(), Some("node gen.js"));
+        assert_eq!(config.hooks.on_failure.as_deref(), Some("echo failed"));
+        assert_eq!(config.hooks.post_build, None);
+
+        let foo = &config.crates[0];
+        assert_eq!(foo.hooks.post_build.as_deref(), Some("cp -r pkg dist"));
+        assert_eq!(foo.hooks.pre_build, None);
+        assert_eq!(foo.args.features, Some(vec!["a".into()]));
+    }
+}
+
+#[cfg(test)]
+mod watch_strategy_tests {
+    use super::*;
+
+    #[test]
+    fn parse_strategy() {
+        let config: CrateConfig =
+            toml::from_str("name = \"foo\"\n[watch]\nstrategy = \"coalesce\"").unwrap();
+        let watch = config.watch.unwrap();
+        assert_eq!(watch.strategy, Some(WatchStrategy::Coalesce));
+
+        let config: CrateConfig = toml::from_str("name = \"foo\"").unwrap();
+        let strategy = config.watch.unwrap().strategy.unwrap_or_default();
+        assert_eq!(strategy, WatchStrategy::Abort);
+    }
+}
@@ -1,20 +1,152 @@
 //! rsw build
 
-use std::path::PathBuf;
-use std::process::Command;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use path_clean::PathClean;
 
-use crate::config::CrateConfig;
-use crate::core::Link;
-use crate::core::RswInfo;
-use crate::utils::{get_crate_metadata, get_pkg, print, rsw_watch_file};
+use crate::config::{CrateConfig, HooksOptions, SizeBudget};
+use crate::core::{
+    parse_size, strip_ansi, Diagnostic, Event, Fingerprint, HookStage, Hooks, Link, Manifest,
+    ManifestCrate, Package, RswErr, RswInfo, SizeReport,
+};
+use crate::utils::{
+    check_env_cmd, get_crate_metadata, get_pkg, get_root, kill_process_tree, new_process_group,
+    path_exists, print, rsw_watch_file,
+};
+
+// `threads = true`: the target features for shared memory,
+// and the standard library rebuilt with them
+static THREADS_TARGET_FEATURES: &str = "-C target-feature=+atomics,+bulk-memory,+mutable-globals";
+static THREADS_BUILD_STD: &str = "build-std=panic_abort,std";
+
+/// The outcome of building a crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildStatus {
+    Ok,
+    Failed,
+    /// inputs unchanged since the last successful build
+    UpToDate,
+    /// not built, a dependency failed or the run stopped early
+    Skipped,
+    /// stopped by `BuildCancel`, e.g. a file changed in `watch` mode
+    Cancelled,
+}
+
+impl BuildStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuildStatus::Ok => "ok",
+            BuildStatus::Failed => "failed",
+            BuildStatus::UpToDate => "up-to-date",
+            BuildStatus::Skipped => "skipped",
+            BuildStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildResult {
+    pub name: String,
+    pub status: BuildStatus,
+    pub duration: Duration,
+    /// sizes of the generated files, unknown if the build failed
+    pub size: Option<SizeReport>,
+    /// compiler messages of a failed `wasm-pack build`
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl BuildResult {
+    pub fn skipped(name: &str) -> BuildResult {
+        BuildResult {
+            name: name.into(),
+            status: BuildStatus::Skipped,
+            duration: Duration::ZERO,
+            size: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self.status, BuildStatus::Ok | BuildStatus::UpToDate)
+    }
+}
+
+// the wasm-pack processes of this rsw, stopped on Ctrl-C or SIGTERM
+static RUNNING: Mutex<Vec<BuildCancel>> = Mutex::new(Vec::new());
+// set by `BuildCancel::cancel_all`, builds started afterwards are cancelled at once
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// Stops a running build, the wasm-pack process tree is killed
+#[derive(Debug, Clone, Default)]
+pub struct BuildCancel(Arc<AtomicBool>);
+
+impl BuildCancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Cancel every running build and wait up to `timeout` for their process trees to be killed
+    pub fn cancel_all(timeout: Duration) {
+        STOPPING.store(true, Ordering::SeqCst);
+        for cancel in RUNNING.lock().unwrap().iter() {
+            cancel.cancel();
+        }
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline && !RUNNING.lock().unwrap().is_empty() {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+// a wasm-pack process, registered until it has exited
+struct Running(BuildCancel);
+
+impl Running {
+    fn new(cancel: &BuildCancel) -> Running {
+        RUNNING.lock().unwrap().push(cancel.clone());
+        if STOPPING.load(Ordering::SeqCst) {
+            cancel.cancel();
+        }
+        Running(cancel.clone())
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let cancel = &self.0 .0;
+        RUNNING
+            .lock()
+            .unwrap()
+            .retain(|i| !Arc::ptr_eq(&i.0, cancel));
+    }
+}
+
+// how a wasm-pack process ended
+enum Exit {
+    Status(ExitStatus),
+    Cancelled,
+    Timeout,
+}
 
 pub struct Build {
     config: CrateConfig,
     rsw_type: String,
     cli: String,
     is_link: bool,
+    force: bool,
+    hooks: HooksOptions,
+    cancel: BuildCancel,
 }
 
 impl Build {
@@ -24,27 +156,63 @@ impl Build {
             rsw_type: rsw_type.into(),
             cli,
             is_link,
+            force: false,
+            hooks: HooksOptions::default(),
+            cancel: BuildCancel::default(),
+        }
+    }
+
+    /// build even if the crate is up to date
+    pub fn force(mut self, force: bool) -> Build {
+        self.force = force;
+        self
+    }
+
+    /// the hooks of `rsw.toml` run for every crate
+    pub fn hooks(mut self, hooks: HooksOptions) -> Build {
+        self.hooks = hooks;
+        self
+    }
+
+    /// stop the build from another thread
+    pub fn cancel(mut self, cancel: BuildCancel) -> Build {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn init(&self) -> BuildResult {
+        let start = Instant::now();
+        Event::BuildStarted {
+            name: self.config.name.clone(),
+            mode: self.rsw_type.clone(),
         }
+        .emit();
+
+        let (status, size, diagnostics) = self.build();
+        let result = BuildResult {
+            name: self.config.name.clone(),
+            status,
+            duration: start.elapsed(),
+            size,
+            diagnostics,
+        };
+        if let Some(event) = Event::build_result(&result, &self.rsw_type) {
+            event.emit();
+        }
+        result
     }
 
-    pub fn init(&self) -> bool {
+    fn build(&self) -> (BuildStatus, Option<SizeReport>, Vec<Diagnostic>) {
         let config = &self.config;
         let rsw_type = &self.rsw_type;
         let name = &config.name;
         let root = config.root.as_ref().unwrap();
-        let out_dir = config.out_dir.as_ref().unwrap();
         let crate_root = PathBuf::from(root).join(name).clean();
         let build_name = crate_root.to_string_lossy().to_string();
-        let target = config.target.as_ref().unwrap();
         let scope = config.scope.as_ref();
-        let mut args = vec![
-            "build",
-            &build_name,
-            "--out-dir",
-            out_dir,
-            "--target",
-            target,
-        ];
+        let outputs = config.outputs();
+        // options shared by all targets
+        let mut args = vec![];
 
         // profile
         let mut profile = config.build.as_ref().unwrap().profile.as_ref().unwrap();
@@ -52,7 +220,7 @@ impl Build {
             profile = config.watch.as_ref().unwrap().profile.as_ref().unwrap();
         }
         let arg_profile = format!("--{}", profile);
-        args.push(&arg_profile);
+        args.push(arg_profile.as_str());
 
         // scope
         let (_, scope2) = get_pkg(&self.config.name);
@@ -64,57 +232,578 @@ impl Build {
             args.push(scope.unwrap());
         }
 
-        let metadata = get_crate_metadata(name, crate_root);
-        info!("🚧  wasm-pack {}", args.join(" "));
+        // wasm-pack and cargo arguments
+        let (mode_args, wasm_opt) = match rsw_type == "watch" {
+            true => {
+                let watch = config.watch.as_ref().unwrap();
+                (&watch.args, watch.wasm_opt.as_ref())
+            }
+            false => {
+                let build = config.build.as_ref().unwrap();
+                (&build.args, build.wasm_opt.as_ref())
+            }
+        };
+
+        // wasm-opt is run by rsw instead of wasm-pack
+        if wasm_opt.is_some() {
+            args.push("--no-opt");
+        }
+        let wasm_opt_args = wasm_opt.filter(|o| o.is_enabled()).map(|o| {
+            let mut opt_args = o.args();
+            if config.is_threads() {
+                opt_args.extend(["--enable-threads".into(), "--enable-bulk-memory".into()]);
+            }
+            opt_args
+        });
+
+        let extra_args = config.args.merge(mode_args);
+        let wasm_pack_args = extra_args.wasm_pack_args();
+        let cargo_args = extra_args.cargo_args();
+        args.extend(wasm_pack_args.iter().map(String::as_str));
+        args.push("--");
+        args.push("--message-format=json-diagnostic-rendered-ansi");
+        if config.is_threads() {
+            args.extend(["-Z", THREADS_BUILD_STD]);
+        }
+        args.extend(cargo_args.iter().map(String::as_str));
+        let envs = match config.is_threads() {
+            true => threads_env(
+                env::var("RUSTFLAGS").ok(),
+                env::var("RUSTUP_TOOLCHAIN").ok(),
+            ),
+            false => vec![],
+        };
+
+        // one `wasm-pack build` for each target
+        let builds: Vec<Vec<&str>> = outputs
+            .iter()
+            .map(|(target, out_dir)| {
+                let target_args = [
+                    "build",
+                    &build_name,
+                    "--out-dir",
+                    out_dir,
+                    "--target",
+                    target,
+                ];
+                [&target_args[..], &args[..]].concat()
+            })
+            .collect();
+
+        let metadata = get_crate_metadata(name, crate_root.clone());
+        let targets: Vec<&str> = outputs.iter().map(|(t, _)| t.as_str()).collect();
+        let mut fingerprint_args = builds.join(&"&&");
+        if let Some(opt_args) = &wasm_opt_args {
+            fingerprint_args.extend(["&&", "wasm-opt"]);
+            fingerprint_args.extend(opt_args.iter().map(String::as_str));
+        }
+        let out_dirs: Vec<PathBuf> = outputs.iter().map(|(_, o)| crate_root.join(o)).collect();
+
+        let hooks = Hooks::new(&self.hooks, config, profile, rsw_type);
+        let watch_paths = config.watch.as_ref().unwrap().paths.clone();
+        let (mut is_ok, fingerprint) = pre_build(
+            &hooks,
+            &get_root().join(&crate_root),
+            profile,
+            &targets.join(","),
+            &fingerprint_args,
+            &watch_paths.unwrap_or_default(),
+        );
+        let mut size = None;
+        let mut failed_diagnostics = Vec::new();
+        let deadline = config
+            .timeout
+            .map(|secs| Instant::now() + Duration::from_secs(secs));
+
+        if is_ok && !self.force && self.is_fresh(&fingerprint, &out_dirs) {
+            print(RswInfo::CrateFresh(name.into(), rsw_type.into()));
+            self.update_manifest(BuildStatus::UpToDate);
+            self.link();
+            print(RswInfo::SplitLine);
+            return (BuildStatus::UpToDate, SizeReport::last(name), Vec::new());
+        }
+
+        if !is_ok {
+            print(RswInfo::CrateFail(name.into(), rsw_type.into()));
+        }
 
-        let status = Command::new("wasm-pack")
-            .args(&args)
-            .status()
-            .expect("failed to execute process");
+        for args in &builds {
+            if !is_ok {
+                break;
+            }
+            let env_prefix: Vec<String> = envs
+                .iter()
+                .map(|(k, v)| format!("{}={:?} ", k, v))
+                .collect();
+            info!("🚧  {}wasm-pack {}", env_prefix.concat(), args.join(" "));
 
-        println!(" ");
+            let (exit, output, diagnostics) = wasm_pack(args, &envs, &self.cancel, deadline);
 
-        let mut is_ok = true;
+            print(" ");
 
-        if let Some(code) = status.code() {
-            if code == 0 {
-                print(RswInfo::CrateOk(
-                    name.into(),
-                    rsw_type.into(),
-                    metadata["package"]["version"].to_string(),
-                ));
-            } else {
-                let output = Command::new("wasm-pack")
-                    .args(&args)
-                    .stderr(std::process::Stdio::piped())
-                    .output()
-                    .unwrap();
+            let success = match exit {
+                Exit::Status(status) => status.success(),
+                Exit::Cancelled => {
+                    print(RswInfo::CrateCancel(name.into(), rsw_type.into()));
+                    Fingerprint::remove(name);
+                    self.update_manifest(BuildStatus::Cancelled);
+                    print(RswInfo::SplitLine);
+                    return (BuildStatus::Cancelled, None, Vec::new());
+                }
+                Exit::Timeout => {
+                    print(RswErr::BuildTimeout(name.into(), config.timeout.unwrap()));
+                    false
+                }
+            };
 
-                let err = std::str::from_utf8(&output.stderr).unwrap();
+            if !success {
                 let info_content = format!(
                     "[RSW::ERR]\n[RSW::NAME] :~> {}\n[RSW::BUILD] :~> wasm-pack {}",
                     name,
                     &args.join(" ")
                 );
-                rsw_watch_file(info_content.as_bytes(), err.as_bytes(), "err".into()).unwrap();
+                rsw_watch_file(info_content.as_bytes(), &output, "err".into()).unwrap();
+                if let Err(e) = Diagnostic::save(name, &diagnostics) {
+                    warn!("{} diagnostics: {}", name, e);
+                }
                 print(RswInfo::CrateFail(name.into(), rsw_type.into()));
+                Fingerprint::remove(name);
+
+                failed_diagnostics = diagnostics;
+                is_ok = false;
+                break;
+            }
+        }
 
+        if is_ok {
+            if let Some(opt_args) = &wasm_opt_args {
+                if !self.wasm_opt(opt_args, &out_dirs) {
+                    print(RswInfo::CrateFail(name.into(), rsw_type.into()));
+                    Fingerprint::remove(name);
+                    is_ok = false;
+                }
+            }
+        }
+
+        // one npm package for all targets
+        if is_ok && config.is_combined() {
+            let link_dir = config.link_dir();
+            let pkg_outputs = outputs
+                .iter()
+                .map(|(target, out_dir)| {
+                    let dir = Path::new(out_dir).strip_prefix(&link_dir).unwrap();
+                    (target.clone(), dir.to_string_lossy().to_string())
+                })
+                .collect();
+            if let Err(e) = Package::new(crate_root.join(&link_dir), pkg_outputs).init() {
+                print(RswErr::Package(name.into(), e));
+                print(RswInfo::CrateFail(name.into(), rsw_type.into()));
+                Fingerprint::remove(name);
+                is_ok = false;
+            }
+        }
+
+        // wasm size, compared to the previous build
+        if is_ok {
+            let report = SizeReport::new(&out_dirs);
+            print(RswInfo::CrateSize(
+                name.into(),
+                report.clone(),
+                SizeReport::last(name),
+            ));
+            if let Err(e) = report.append(name) {
+                warn!("{} size history: {}", name, e);
+            }
+            if !self.check_size_budget(&report) {
+                print(RswInfo::CrateFail(name.into(), rsw_type.into()));
+                Fingerprint::remove(name);
                 is_ok = false;
             }
+            size = Some(report);
+        }
+
+        if is_ok && !hooks.run(HookStage::PostBuild) {
+            print(RswInfo::CrateFail(name.into(), rsw_type.into()));
+            Fingerprint::remove(name);
+            is_ok = false;
+        }
+
+        if is_ok {
+            if let Err(e) = fingerprint.save(name) {
+                warn!("{} fingerprint: {}", name, e);
+            }
+            if let Err(e) = Diagnostic::save(name, &[]) {
+                warn!("{} diagnostics: {}", name, e);
+            }
+            print(RswInfo::CrateOk(
+                name.into(),
+                rsw_type.into(),
+                metadata["package"]["version"].to_string(),
+            ));
+        }
+
+        if !is_ok {
+            hooks.run(HookStage::OnFailure);
+        }
+
+        let status = if is_ok {
+            BuildStatus::Ok
+        } else {
+            BuildStatus::Failed
+        };
+        self.update_manifest(status);
+
+        self.link();
+
+        print(RswInfo::SplitLine);
+
+        (status, size, failed_diagnostics)
+    }
+
+    fn update_manifest(&self, status: BuildStatus) {
+        let entry = ManifestCrate::new(&self.config, &self.rsw_type, status.as_str());
+        if let Err(e) = Manifest::update(entry) {
+            warn!("{} manifest: {}", self.config.name, e);
+        }
+    }
+
+    // run `wasm-opt` on the `.wasm` files of each output directory
+    fn wasm_opt(&self, args: &[String], out_dirs: &[PathBuf]) -> bool {
+        if !check_env_cmd("wasm-opt") {
+            print(RswErr::WasmOpt);
+            return false;
+        }
+
+        for out_dir in out_dirs {
+            let mut files: Vec<PathBuf> = match fs::read_dir(out_dir) {
+                Ok(entries) => entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "wasm"))
+                    .collect(),
+                Err(_) => continue,
+            };
+            files.sort();
+
+            for file in files {
+                let file_name = file.to_string_lossy();
+                info!(
+                    "🚧  wasm-opt {} {} -o {}",
+                    args.join(" "),
+                    file_name,
+                    file_name
+                );
+                let mut command = Command::new("wasm-opt");
+                command.args(args).arg(&file).arg("-o").arg(&file);
+                if Event::is_output_hidden() {
+                    command.stdout(Stdio::null()).stderr(Stdio::null());
+                } else if Event::is_stdout_reserved() {
+                    command.stdout(io::stderr());
+                }
+                let status = command.status();
+                if !matches!(status, Ok(status) if status.success()) {
+                    return false;
+                }
+            }
         }
 
+        true
+    }
+
+    // `rsw build` fails when the wasm is larger than `size-budget`, `rsw watch` only warns
+    fn check_size_budget(&self, report: &SizeReport) -> bool {
+        let name = &self.config.name;
+        let is_build = self.rsw_type == "build";
+        let budget = match &self.config.size_budget {
+            Some(budget) => budget,
+            None => return true,
+        };
+        let bytes = match budget {
+            SizeBudget::Bytes(bytes) => *bytes,
+            SizeBudget::Text(text) => match parse_size(text) {
+                Some(bytes) => bytes,
+                None => {
+                    print(RswErr::SizeBudgetInvalid(name.into(), text.into()));
+                    return !is_build;
+                }
+            },
+        };
+        if report.wasm.raw <= bytes {
+            return true;
+        }
+        print(RswErr::SizeBudget(name.into(), report.wasm.raw, bytes));
+        !is_build
+    }
+
+    // the inputs match the last successful build and its output still exists
+    fn is_fresh(&self, fingerprint: &Fingerprint, out_dirs: &[PathBuf]) -> bool {
+        let name = &self.config.name;
+        for out_dir in out_dirs {
+            if !path_exists(out_dir) {
+                debug!("{}: rebuild, {} does not exist", name, out_dir.display());
+                return false;
+            }
+        }
+        match Fingerprint::load(name) {
+            Some(prev) => {
+                let changes = fingerprint.changes(&prev);
+                for change in &changes {
+                    debug!("{}: rebuild, {}", name, change);
+                }
+                changes.is_empty()
+            }
+            None => {
+                debug!("{}: rebuild, no previous fingerprint", name);
+                false
+            }
+        }
+    }
+
+    fn link(&self) {
+        let config = &self.config;
         if config.link.unwrap() && self.is_link {
-            let cli = &self.cli;
+            let name = &config.name;
             Link::new(
-                cli.into(),
-                PathBuf::from(root).join(name).join(out_dir),
+                self.cli.clone(),
+                PathBuf::from(config.root.as_ref().unwrap())
+                    .join(name)
+                    .join(config.link_dir()),
                 name.to_string(),
             )
             .init();
         }
+    }
+}
 
-        print(RswInfo::SplitLine);
+// run the pre-build hooks before taking the fingerprint,
+// the sources they generate are inputs of the build
+fn pre_build(
+    hooks: &Hooks,
+    crate_root: &Path,
+    profile: &str,
+    target: &str,
+    args: &[&str],
+    paths: &[String],
+) -> (bool, Fingerprint) {
+    let is_ok = hooks.run(HookStage::PreBuild);
+    let fingerprint = Fingerprint::new(crate_root, profile, target, args, paths);
+    (is_ok, fingerprint)
+}
+
+// the environment of `wasm-pack` for `threads = true`: a nightly toolchain,
+// unless `RUSTUP_TOOLCHAIN` already picks one, and the shared memory target features
+fn threads_env(rustflags: Option<String>, toolchain: Option<String>) -> Vec<(String, String)> {
+    let rustflags = match rustflags.filter(|f| !f.trim().is_empty()) {
+        Some(flags) => format!("{} {}", flags, THREADS_TARGET_FEATURES),
+        None => THREADS_TARGET_FEATURES.into(),
+    };
+    let toolchain = toolchain
+        .filter(|t| t.starts_with("nightly"))
+        .unwrap_or_else(|| "nightly".into());
+    vec![
+        ("RUSTUP_TOOLCHAIN".into(), toolchain),
+        ("RUSTFLAGS".into(), rustflags),
+    ]
+}
+
+// run `wasm-pack`, its output is streamed to the terminal and captured at the same time,
+// cargo's json messages on stdout are collected as diagnostics and printed as rendered text,
+// its process tree is killed when the build is cancelled or past `deadline`
+fn wasm_pack(
+    args: &[&str],
+    envs: &[(String, String)],
+    cancel: &BuildCancel,
+    deadline: Option<Instant>,
+) -> (Exit, Vec<u8>, Vec<Diagnostic>) {
+    let running = Running::new(cancel);
+    let mut child = new_process_group(&mut Command::new("wasm-pack"))
+        .args(args)
+        .envs(envs.iter().map(|(k, v)| (k, v)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to execute process");
+
+    let captured = Arc::new(Mutex::new(Vec::new()));
+
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr_captured = captured.clone();
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buf = [0; 1024];
+        loop {
+            match stderr.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if !Event::is_output_hidden() {
+                        let _ = io::stderr().write_all(&buf[..n]);
+                    }
+                    stderr_captured.lock().unwrap().extend_from_slice(&buf[..n]);
+                }
+            }
+        }
+    });
+
+    let stdout = child.stdout.take().unwrap();
+    let stdout_captured = captured.clone();
+    let stdout_reader = std::thread::spawn(move || {
+        let mut diagnostics = Vec::new();
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(mut diagnostic) = Diagnostic::parse(&line) {
+                if let Some(rendered) = diagnostic.rendered.take() {
+                    // colored like rustc's own output on a terminal, recorded without colors
+                    let plain = strip_ansi(&rendered);
+                    if !Event::is_output_hidden() {
+                        let text = match io::stderr().is_terminal() {
+                            true => &rendered,
+                            false => &plain,
+                        };
+                        let _ = io::stderr().write_all(text.as_bytes());
+                    }
+                    stdout_captured
+                        .lock()
+                        .unwrap()
+                        .extend_from_slice(plain.as_bytes());
+                    diagnostic.rendered = Some(plain);
+                }
+                diagnostics.push(diagnostic);
+            } else if !line.starts_with('{') {
+                print(&line);
+                let mut captured = stdout_captured.lock().unwrap();
+                captured.extend_from_slice(line.as_bytes());
+                captured.push(b'\n');
+            }
+        }
+        diagnostics
+    });
+
+    let exit = wait(&mut child, cancel, deadline);
+    drop(running);
+    let diagnostics = stdout_reader.join().unwrap_or_default();
+    let _ = stderr_reader.join();
+    let captured = captured.lock().unwrap().clone();
+
+    (exit, captured, diagnostics)
+}
+
+// wait for a process started with `new_process_group`,
+// its process tree is killed when the build is cancelled or past `deadline`
+fn wait(child: &mut Child, cancel: &BuildCancel, deadline: Option<Instant>) -> Exit {
+    loop {
+        if let Some(status) = child.try_wait().expect("failed to execute process") {
+            return Exit::Status(status);
+        }
+        let exit = if cancel.is_cancelled() {
+            Exit::Cancelled
+        } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Exit::Timeout
+        } else {
+            std::thread::sleep(Duration::from_millis(50));
+            continue;
+        };
+        kill_process_tree(child);
+        let _ = child.wait();
+        return exit;
+    }
+}
+
+#[cfg(test)]
+mod threads_env_tests {
+    use super::*;
+
+    #[test]
+    fn nightly_and_target_features() {
+        let envs = threads_env(None, Some("stable".into()));
+        assert_eq!(envs[0], ("RUSTUP_TOOLCHAIN".into(), "nightly".into()));
+        assert_eq!(
+            envs[1],
+            ("RUSTFLAGS".into(), THREADS_TARGET_FEATURES.into())
+        );
+    }
+
+    #[test]
+    fn keep_user_settings() {
+        let envs = threads_env(
+            Some("-C opt-level=s".into()),
+            Some("nightly-2024-08-02".into()),
+        );
+        assert_eq!(envs[0].1, "nightly-2024-08-02");
+        assert_eq!(
+            envs[1].1,
+            format!("-C opt-level=s {}", THREADS_TARGET_FEATURES)
+        );
+    }
+}
+
+#[cfg(test)]
+mod pre_build_tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_generated_sources() {
+        let ws = env::temp_dir().join("rsw_pre_build_tests");
+        let _ = fs::remove_dir_all(&ws);
+        fs::create_dir_all(ws.join("c/src")).unwrap();
+        fs::write(ws.join("c/Cargo.toml"), "[package]\nname = \"c\"\n").unwrap();
+        fs::write(ws.join("c/src/lib.rs"), "include!(\"gen.rs\");\n").unwrap();
 
-        is_ok
+        let config: CrateConfig =
+            toml::from_str(&format!("name = \"c\"\nroot = {:?}", ws.to_string_lossy())).unwrap();
+        let global = HooksOptions {
+            pre_build: Some(format!("cp {0}/schema.txt {0}/c/src/gen.rs", ws.display())),
+            ..HooksOptions::default()
+        };
+        let hooks = Hooks::new(&global, &config, "release", "build");
+        let run = |schema: &str| {
+            fs::write(ws.join("schema.txt"), schema).unwrap();
+            pre_build(&hooks, &ws.join("c"), "release", "web", &[], &[])
+        };
+
+        let (is_ok, prev) = run("pub const A: u8 = 1;");
+        assert!(is_ok);
+        let (is_ok, next) = run("pub const A: u8 = 2;");
+        let _ = fs::remove_dir_all(&ws);
+
+        assert!(is_ok);
+        let gen = ws.join("c/src/gen.rs");
+        assert_eq!(
+            next.changes(&prev),
+            vec![format!("{} changed", gen.display())]
+        );
+    }
+}
+
+#[cfg(all(test, unix))]
+mod cancel_all_tests {
+    use super::*;
+
+    #[test]
+    fn kill_running_process_trees() {
+        // a child in the background, like cargo under wasm-pack
+        let mut child = new_process_group(&mut Command::new("sh"))
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let sleep_pid: i32 = line.trim().parse().unwrap();
+
+        let cancel = BuildCancel::default();
+        let running = Running::new(&cancel);
+        let stop = std::thread::spawn(|| BuildCancel::cancel_all(Duration::from_secs(5)));
+        assert!(matches!(wait(&mut child, &cancel, None), Exit::Cancelled));
+        drop(running);
+        stop.join().unwrap();
+        STOPPING.store(false, Ordering::SeqCst);
+
+        assert!(RUNNING.lock().unwrap().is_empty());
+        // reaped by init once killed, signal 0 only checks that it exists
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while unsafe { libc::kill(sleep_pid, 0) } == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_ne!(unsafe { libc::kill(sleep_pid, 0) }, 0);
     }
 }
@@ -1,15 +1,18 @@
 //! rsw command parse
 
-use clap::{AppSettings, Parser, Subcommand};
+use clap::{AppSettings, ArgEnum, Args, Parser, Subcommand};
 use path_clean::PathClean;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{mpsc::channel, Arc};
 
 use crate::config::{CrateConfig, RswConfig};
-use crate::core::{Build, Clean, Create, Init, Link, RswInfo, Watch};
+use crate::core::{
+    Build, BuildResult, BuildStatus, Clean, Create, Daemon, Dashboard, DepGraph, Event, Init, Link,
+    LiveReload, Manifest, ManifestCrate, RswInfo, Serve, Watch, WatchCallback,
+};
 use crate::utils::{init_rsw_crates, print, rsw_watch_file};
 
 #[derive(Parser)]
@@ -26,9 +29,13 @@ pub enum Commands {
     /// generate `rsw.toml` configuration file
     Init,
     /// build rust crates, useful for shipping to production
-    Build,
+    Build(BuildArgs),
     /// automatically rebuilding local changes, useful for development and debugging
-    Watch,
+    Watch(WatchArgs),
+    /// `rsw watch` with a local server, pages reload after each successful build
+    Serve(ServeArgs),
+    /// `rsw watch` controlled with JSON-RPC on a Unix domain socket or stdio
+    Daemon(DaemonArgs),
     /// clean - `npm link` and `wasm-pack build`
     Clean,
     /// quickly generate a crate with `wasm-pack new`, or set a custom template in `rsw.toml [new]`
@@ -44,25 +51,95 @@ pub enum Commands {
     },
 }
 
+/// `rsw build` and `rsw watch` options
+#[derive(Args)]
+pub struct BuildArgs {
+    /// the maximum number of crates to build in parallel, overrides `jobs` in `rsw.toml`
+    #[clap(short = 'j', long)]
+    jobs: Option<usize>,
+    /// build all crates, even those whose inputs have not changed since the last build
+    #[clap(short = 'f', long)]
+    force: bool,
+    /// stop starting new builds after the first crate fails
+    #[clap(long, overrides_with = "keep-going")]
+    fail_fast: bool,
+    /// build every crate that does not depend on a failed one (default)
+    #[clap(long, overrides_with = "fail-fast")]
+    keep_going: bool,
+    /// `json`: print newline-delimited JSON events on stdout, the other output goes to stderr
+    #[clap(long, arg_enum, default_value = "human")]
+    message_format: MessageFormat,
+}
+
+impl BuildArgs {
+    // the last of `--fail-fast` and `--keep-going` wins
+    fn is_fail_fast(&self) -> bool {
+        self.fail_fast && !self.keep_going
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+/// `rsw watch` options
+#[derive(Args)]
+pub struct WatchArgs {
+    #[clap(flatten)]
+    build: BuildArgs,
+    /// poll the files for changes, overrides `[watch] poll` in `rsw.toml`
+    #[clap(long)]
+    poll: bool,
+    /// show a dashboard with one row per crate instead of the build output
+    #[clap(long, conflicts_with = "message-format")]
+    tui: bool,
+}
+
+/// `rsw daemon` options
+#[derive(Args)]
+pub struct DaemonArgs {
+    #[clap(flatten)]
+    watch: WatchArgs,
+    /// the Unix domain socket to listen on
+    #[clap(long, default_value = ".rsw/rsw.sock")]
+    socket: PathBuf,
+    /// serve a single client on stdin and stdout instead of the socket
+    #[clap(long, conflicts_with_all = &["socket", "tui", "message-format"])]
+    stdio: bool,
+}
+
+/// `rsw serve` options
+#[derive(Args)]
+pub struct ServeArgs {
+    #[clap(flatten)]
+    watch: WatchArgs,
+    /// the port to listen on
+    #[clap(short = 'p', long, default_value = "8080")]
+    port: u16,
+    /// the address to listen on
+    #[clap(long, default_value = "127.0.0.1")]
+    host: String,
+}
+
 impl Cli {
     pub fn init() {
         match &Cli::parse().command {
             Commands::Init => Cli::rsw_init(),
             Commands::Clean => Cli::rsw_clean(),
-            Commands::Build => {
-                Cli::rsw_build();
+            Commands::Build(args) => {
+                Cli::rsw_build(args);
             }
 
-            Commands::Watch => {
-                Cli::rsw_watch(Some(Arc::new(|a, b| {
-                    let name = &a.name;
-                    let path = &b.to_string_lossy().to_string();
-                    let info_content = format!(
-                        "[RSW::OK]\n[RSW::NAME] :~> {}\n[RSW::PATH] :~> {}",
-                        name, path
-                    );
-                    rsw_watch_file(info_content.as_bytes(), "".as_bytes(), "info".into()).unwrap();
-                })));
+            Commands::Watch(args) => {
+                Cli::rsw_watch(args, Some(Arc::new(Cli::watch_info)));
+            }
+            Commands::Serve(args) => {
+                Cli::rsw_serve(args);
+            }
+            Commands::Daemon(args) => {
+                Cli::rsw_daemon(args);
             }
             Commands::New {
                 name,
@@ -73,17 +150,77 @@ impl Cli {
             }
         }
     }
-    pub fn rsw_build() {
-        Cli::wp_build(Arc::new(Cli::parse_toml()), "build", true);
+    pub fn rsw_build(args: &BuildArgs) {
+        let config = Cli::parse_build_toml(args);
+        let results = Cli::wp_build(Arc::new(config), "build", true, args);
+
+        print(RswInfo::BuildSummary(results.clone()));
+        if results.iter().any(|r| !r.is_ok()) {
+            std::process::exit(1);
+        }
+    }
+    pub fn rsw_watch(args: &WatchArgs, callback: Option<WatchCallback>) {
+        Cli::watch(args, callback).init();
     }
-    pub fn rsw_watch(
-        callback: Option<Arc<dyn Fn(&CrateConfig, std::path::PathBuf) + Send + Sync + 'static>>,
-    ) {
+    // parse `rsw.toml` and run the initial build
+    fn watch(args: &WatchArgs, callback: Option<WatchCallback>) -> Watch {
+        let mut config = Cli::parse_build_toml(&args.build);
+        if args.poll {
+            let watcher = config.watch.as_mut().unwrap();
+            watcher.poll = Some(true);
+        }
+
         // initial build
-        let config = Arc::new(Cli::parse_toml());
-        Cli::wp_build(config.clone(), "watch", true);
+        let config = Arc::new(config);
+        let results = Cli::wp_build(config.clone(), "watch", true, &args.build);
 
-        Watch::new(config, callback.unwrap()).init();
+        let mut watch = Watch::new(config, callback.unwrap());
+        if args.tui {
+            let (tx, rx) = channel();
+            Dashboard::new(watch.config(), &results, tx).init();
+            watch = watch.requests(rx);
+        }
+        watch
+    }
+    pub fn rsw_serve(args: &ServeArgs) {
+        let reload = LiveReload::default();
+        let page_reload = reload.clone();
+        let watch = Cli::watch(
+            &args.watch,
+            Some(Arc::new(move |a, b| {
+                Cli::watch_info(a, b);
+                page_reload.reload(&a.name);
+            })),
+        );
+
+        Serve::new(watch.config(), &args.host, args.port, reload).init();
+        watch.init();
+    }
+    pub fn rsw_daemon(args: &DaemonArgs) {
+        let socket = match args.stdio {
+            true => {
+                Event::reserve_stdout();
+                None
+            }
+            false => Some(args.socket.clone()),
+        };
+        // the clients are served after the initial build, which cannot be cancelled,
+        // its events are kept until then
+        let events = Event::subscribe();
+        let watch = Cli::watch(&args.watch, Some(Arc::new(Cli::watch_info)));
+
+        let (tx, rx) = channel();
+        Daemon::new(watch.config(), tx, socket).init(events);
+        watch.requests(rx).init();
+    }
+    // `.rsw/rsw.info`, written after each successful rebuild
+    fn watch_info(config: &CrateConfig, path: PathBuf) {
+        let info_content = format!(
+            "[RSW::OK]\n[RSW::NAME] :~> {}\n[RSW::PATH] :~> {}",
+            config.name,
+            path.to_string_lossy()
+        );
+        rsw_watch_file(info_content.as_bytes(), "".as_bytes(), "info".into()).unwrap();
     }
     pub fn rsw_init() {
         Init::init().unwrap();
@@ -103,12 +240,16 @@ impl Cli {
     pub fn parse_toml() -> RswConfig {
         let config = RswConfig::new().unwrap();
         trace!("{:#?}", config);
-
+        Cli::init_crates(&config);
+        config
+    }
+    // `.rsw/rsw.crates`, and drop the removed crates from the manifest
+    pub fn init_crates(config: &RswConfig) {
         let mut crates = Vec::new();
         for i in &config.crates {
             let name = &i.name;
             let root = i.root.as_ref().unwrap();
-            let out = i.out_dir.as_ref().unwrap();
+            let out = i.link_dir();
             let crate_out = PathBuf::from(root).join(name).join(out);
 
             crates.push(format!(
@@ -116,17 +257,50 @@ impl Cli {
                 name,
                 crate_out.clean().to_string_lossy()
             ));
+
+            // several targets: `<name>:<target> :~> <path>`
+            let outputs = i.outputs();
+            if outputs.len() > 1 {
+                for (target, out) in outputs {
+                    let crate_out = PathBuf::from(root).join(name).join(out);
+                    crates.push(format!(
+                        "{}:{} :~> {}",
+                        name,
+                        target,
+                        crate_out.clean().to_string_lossy()
+                    ));
+                }
+            }
         }
         init_rsw_crates(crates.join("\n").as_bytes()).unwrap();
 
+        let names: Vec<String> = config.crates.iter().map(|i| i.name.clone()).collect();
+        if let Err(e) = Manifest::retain(&names) {
+            warn!("manifest: {}", e);
+        }
+    }
+    // command line options take precedence over `rsw.toml`
+    fn parse_build_toml(args: &BuildArgs) -> RswConfig {
+        Event::set_json(args.message_format == MessageFormat::Json);
+        let mut config = Cli::parse_toml();
+        if args.jobs.is_some() {
+            config.jobs = args.jobs;
+        }
         config
     }
-    pub fn wp_build(config: Arc<RswConfig>, rsw_type: &str, is_link: bool) {
+    pub fn wp_build(
+        config: Arc<RswConfig>,
+        rsw_type: &str,
+        is_link: bool,
+        args: &BuildArgs,
+    ) -> Vec<BuildResult> {
         let crates_map = Rc::new(RefCell::new(HashMap::new()));
 
         let cli = &config.cli.to_owned().unwrap_or_else(|| "npm".to_string());
         let mut has_crates = false;
         let mut is_exit = true;
+        let mut builds = Vec::new();
+        let mut crates = Vec::new();
 
         for i in &config.crates {
             let run_build = rsw_type == "build" && i.build.as_ref().unwrap().run.unwrap();
@@ -139,14 +313,19 @@ impl Cli {
                     let rsw_crate = i.clone();
                     let crate_path = PathBuf::from(rsw_crate.root.as_ref().unwrap())
                         .join(&i.name)
-                        .join(rsw_crate.out_dir.unwrap());
+                        .join(rsw_crate.link_dir());
                     crates_map.borrow_mut().insert(
                         rsw_crate.name.to_string(),
                         crate_path.to_string_lossy().to_string(),
                     );
                 }
 
-                Build::new(i.clone(), rsw_type, cli.into(), is_link).init();
+                builds.push(
+                    Build::new(i.clone(), rsw_type, cli.into(), is_link)
+                        .force(args.force)
+                        .hooks(config.hooks.clone()),
+                );
+                crates.push(i.clone());
             }
         }
 
@@ -156,6 +335,29 @@ impl Cli {
             std::process::exit(1);
         }
 
+        let graph = DepGraph::new(&crates).unwrap_or_else(|e| {
+            print(e);
+            std::process::exit(1);
+        });
+        let jobs = config.jobs.unwrap_or(1);
+        let results = Cli::run_builds(
+            |idx| builds[idx].init(),
+            |idx, dep| {
+                let name = &crates[idx].name;
+                let dep = dep.map(|dep| crates[dep].name.clone());
+                print(RswInfo::CrateSkip(name.clone(), rsw_type.into(), dep));
+                let entry =
+                    ManifestCrate::new(&crates[idx], rsw_type, BuildStatus::Skipped.as_str());
+                if let Err(e) = Manifest::update(entry) {
+                    warn!("{} manifest: {}", name, e);
+                }
+            },
+            &crates,
+            &graph,
+            jobs,
+            args.is_fail_fast(),
+        );
+
         // npm link foo bar ...
         let crates = crates_map.borrow();
         if cli == "npm" && has_crates {
@@ -164,5 +366,143 @@ impl Cli {
                 Vec::from_iter(crates.values().map(|i| i.into())),
             );
         }
+
+        results
+    }
+    // run `build` for each crate, dependencies first and at most `jobs` at the same time,
+    // with `fail_fast` no new build starts after a failure,
+    // `skip` is called once for each crate that is not built, with the failed dependency
+    fn run_builds(
+        build: impl Fn(usize) -> BuildResult + Sync,
+        mut skip: impl FnMut(usize, Option<usize>),
+        crates: &[CrateConfig],
+        graph: &DepGraph,
+        jobs: usize,
+        fail_fast: bool,
+    ) -> Vec<BuildResult> {
+        let jobs = jobs.max(1);
+        let mut results: Vec<BuildResult> = crates
+            .iter()
+            .map(|i| BuildResult::skipped(&i.name))
+            .collect();
+        let mut stopped = false;
+        // the number of unfinished dependencies of each crate
+        let mut remaining: Vec<usize> = (0..graph.len()).map(|i| graph.deps(i).len()).collect();
+        // the failed dependency that prevents a crate from building
+        let mut failed_dep: Vec<Option<usize>> = vec![None; graph.len()];
+        let mut skipped = vec![false; graph.len()];
+        let mut ready: VecDeque<usize> = (0..graph.len()).filter(|i| remaining[*i] == 0).collect();
+        let (tx, rx) = channel();
+
+        // unlock the dependents of a finished crate
+        let mut settle = |idx: usize,
+                          failed: Option<usize>,
+                          failed_dep: &mut Vec<Option<usize>>,
+                          ready: &mut VecDeque<usize>| {
+            for dependent in graph.dependents(idx) {
+                if failed_dep[*dependent].is_none() {
+                    failed_dep[*dependent] = failed;
+                }
+                remaining[*dependent] -= 1;
+                if remaining[*dependent] == 0 {
+                    ready.push_back(*dependent);
+                }
+            }
+        };
+
+        let build = &build;
+        std::thread::scope(|s| {
+            let mut running = 0;
+            loop {
+                while running < jobs {
+                    if stopped {
+                        break;
+                    }
+                    let idx = match ready.pop_front() {
+                        Some(idx) => idx,
+                        None => break,
+                    };
+                    if let Some(dep) = failed_dep[idx] {
+                        skip(idx, Some(dep));
+                        skipped[idx] = true;
+                        settle(idx, Some(dep), &mut failed_dep, &mut ready);
+                        continue;
+                    }
+                    let tx = tx.clone();
+                    s.spawn(move || tx.send((idx, build(idx))).unwrap());
+                    running += 1;
+                }
+                if running == 0 {
+                    break;
+                }
+                let (idx, result) = rx.recv().unwrap();
+                running -= 1;
+                let failed = if result.is_ok() { None } else { Some(idx) };
+                stopped |= fail_fast && failed.is_some();
+                results[idx] = result;
+                settle(idx, failed, &mut failed_dep, &mut ready);
+            }
+        });
+
+        // not started because of `fail_fast`
+        for (idx, result) in results.iter().enumerate() {
+            if result.status == BuildStatus::Skipped && !skipped[idx] {
+                skip(idx, failed_dep[idx]);
+            }
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod run_builds_tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn result(name: &str, status: BuildStatus) -> BuildResult {
+        BuildResult {
+            status,
+            duration: Duration::ZERO,
+            ..BuildResult::skipped(name)
+        }
+    }
+
+    // foo fails, bar depends on foo, baz is independent and comes last
+    fn run(fail_fast: bool) -> (Vec<BuildStatus>, Vec<(usize, Option<usize>)>) {
+        let crates: Vec<CrateConfig> = ["foo", "bar", "baz"]
+            .iter()
+            .map(|name| toml::from_str(&format!("name = \"{}\"", name)).unwrap())
+            .collect();
+        let graph = DepGraph::from_edges(vec![vec![], vec![0], vec![]]);
+        let mut skips = Vec::new();
+        let results = Cli::run_builds(
+            |idx| match idx {
+                0 => result("foo", BuildStatus::Failed),
+                _ => result(&crates[idx].name, BuildStatus::Ok),
+            },
+            |idx, dep| skips.push((idx, dep)),
+            &crates,
+            &graph,
+            1,
+            fail_fast,
+        );
+        (results.iter().map(|r| r.status).collect(), skips)
+    }
+
+    #[test]
+    fn keep_going_skips_dependents() {
+        let (statuses, skips) = run(false);
+        use BuildStatus::*;
+        assert_eq!(statuses, vec![Failed, Skipped, Ok]);
+        assert_eq!(skips, vec![(1, Some(0))]);
+    }
+
+    #[test]
+    fn fail_fast_reports_every_skipped_crate() {
+        let (statuses, skips) = run(true);
+        use BuildStatus::*;
+        assert_eq!(statuses, vec![Failed, Skipped, Skipped]);
+        assert_eq!(skips, vec![(1, Some(0)), (2, None)]);
     }
 }
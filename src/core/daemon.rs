@@ -0,0 +1,420 @@
+//! rsw daemon
+//!
+//! `rsw watch` controlled with JSON-RPC 2.0, one message per line,
+//! on a Unix domain socket or on stdio for a single client.
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::core::{
+    BuildCancel, Diagnostic, Event, RswErr, RswInfo, SharedConfig, SizeReport, WatchRequest,
+};
+use crate::utils::print;
+
+// JSON-RPC error codes
+static PARSE_ERROR: i64 = -32700;
+static INVALID_REQUEST: i64 = -32600;
+static METHOD_NOT_FOUND: i64 = -32601;
+static INVALID_PARAMS: i64 = -32602;
+
+type Client = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// The last known state of a crate
+#[derive(Debug, Clone, Serialize)]
+struct CrateStatus {
+    name: String,
+    /// `pending` | `building` | `ok` | `up-to-date` | `failed` | `cancelled`
+    status: String,
+    duration_ms: Option<u128>,
+    size: Option<SizeReport>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl CrateStatus {
+    fn new(name: &str) -> CrateStatus {
+        CrateStatus {
+            name: name.into(),
+            status: "pending".into(),
+            duration_ms: None,
+            size: None,
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+pub struct Daemon {
+    /// the watched crates, follows `rsw.toml`
+    config: SharedConfig,
+    requests: Sender<WatchRequest>,
+    crates: Mutex<BTreeMap<String, CrateStatus>>,
+    subscribers: Mutex<Vec<Client>>,
+    /// the Unix domain socket, stdio if `None`
+    socket: Option<PathBuf>,
+}
+
+impl Daemon {
+    /// Builds are requested through `requests`
+    pub fn new(
+        config: SharedConfig,
+        requests: Sender<WatchRequest>,
+        socket: Option<PathBuf>,
+    ) -> Daemon {
+        let crates = config
+            .get()
+            .crates
+            .iter()
+            .map(|i| (i.name.clone(), CrateStatus::new(&i.name)))
+            .collect();
+        Daemon {
+            config,
+            requests,
+            crates: Mutex::new(crates),
+            subscribers: Mutex::new(Vec::new()),
+            socket,
+        }
+    }
+
+    /// Collect the `events` of `Event::subscribe` and serve the clients in the background
+    pub fn init(self, events: Receiver<Event>) {
+        let daemon = Arc::new(self);
+
+        let subscriber = daemon.clone();
+        std::thread::spawn(move || {
+            for event in events {
+                subscriber.update(&event);
+                subscriber.publish(&event);
+            }
+        });
+
+        match daemon.socket.clone() {
+            Some(path) => daemon.listen(path),
+            None => {
+                // the only client, the daemon stops at the end of stdin
+                let client: Client = Arc::new(Mutex::new(Box::new(io::stdout())));
+                std::thread::spawn(move || {
+                    daemon.serve(BufReader::new(io::stdin()), &client);
+                    daemon.shutdown();
+                });
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn listen(self: Arc<Daemon>, path: PathBuf) {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        let socket = path.to_string_lossy().to_string();
+        // a socket left behind by a daemon that did not shut down
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                let err = io::Error::new(io::ErrorKind::AddrInUse, "another rsw daemon is running");
+                print(RswErr::Daemon(socket, err));
+                std::process::exit(1);
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let listener = UnixListener::bind(&path).unwrap_or_else(|e| {
+            print(RswErr::Daemon(socket.clone(), e));
+            std::process::exit(1);
+        });
+        print(RswInfo::Daemon(socket));
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let writer = match stream.try_clone() {
+                    Ok(writer) => writer,
+                    Err(_) => continue,
+                };
+                let client: Client = Arc::new(Mutex::new(Box::new(writer)));
+                let daemon = self.clone();
+                std::thread::spawn(move || daemon.serve(BufReader::new(stream), &client));
+            }
+        });
+    }
+
+    #[cfg(not(unix))]
+    fn listen(self: Arc<Daemon>, path: PathBuf) {
+        let err = io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix domain sockets are not supported, use `rsw daemon --stdio`",
+        );
+        print(RswErr::Daemon(path.to_string_lossy().into(), err));
+        std::process::exit(1);
+    }
+
+    // answer the requests of a client until it disconnects
+    fn serve<R: BufRead>(&self, reader: R, client: &Client) {
+        for line in reader.lines().map_while(Result::ok) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (response, is_shutdown) = self.handle(&line, client);
+            let is_sent = response.is_none_or(|response| send(client, &response).is_ok());
+            // even if the client is already gone
+            if is_shutdown {
+                self.shutdown();
+            }
+            if !is_sent {
+                break;
+            }
+        }
+        self.unsubscribe(client);
+    }
+
+    // the response to a line, `None` for notifications,
+    // and whether the daemon should stop
+    fn handle(&self, line: &str, client: &Client) -> (Option<Value>, bool) {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return (Some(error(Value::Null, PARSE_ERROR, &e.to_string())), false),
+        };
+        let id = request.get("id").cloned();
+        let method = match request["method"].as_str() {
+            Some(method) => method,
+            None => {
+                let id = id.unwrap_or(Value::Null);
+                return (Some(error(id, INVALID_REQUEST, "missing method")), false);
+            }
+        };
+
+        let result = self.call(method, &request["params"], client);
+        let response = id.map(|id| match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error(id, code, &message),
+        });
+        (response, method == "shutdown")
+    }
+
+    fn call(&self, method: &str, params: &Value, client: &Client) -> Result<Value, (i64, String)> {
+        match method {
+            // the last known state of every crate
+            "status" => {
+                let crates: Vec<CrateStatus> =
+                    self.crates.lock().unwrap().values().cloned().collect();
+                Ok(json!({ "crates": crates }))
+            }
+            // `{ "crate": "<name>" }`, or every watched crate
+            "build" => {
+                let names = match params.get("crate") {
+                    None | Some(Value::Null) => Vec::new(),
+                    Some(Value::String(name)) if self.has_crate(name) => vec![name.clone()],
+                    Some(name) => return Err((INVALID_PARAMS, format!("unknown crate {}", name))),
+                };
+                self.requests
+                    .send(WatchRequest::Build(names.clone()))
+                    .map_err(|e| (INVALID_REQUEST, e.to_string()))?;
+                Ok(json!({ "crates": names }))
+            }
+            // push the events to this client
+            "subscribe" => {
+                self.subscribers.lock().unwrap().push(client.clone());
+                Ok(Value::Bool(true))
+            }
+            "shutdown" => Ok(Value::Null),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        }
+    }
+
+    fn has_crate(&self, name: &str) -> bool {
+        self.config.get().crates.iter().any(|i| i.name == name)
+    }
+
+    fn update(&self, event: &Event) {
+        let mut crates = self.crates.lock().unwrap();
+        let mut status = |name: &String| {
+            crates
+                .entry(name.clone())
+                .or_insert_with(|| CrateStatus::new(name))
+                .clone()
+        };
+        let next = match event {
+            Event::BuildStarted { name, .. } => CrateStatus {
+                status: "building".into(),
+                ..status(name)
+            },
+            Event::BuildFinished {
+                name,
+                status: build_status,
+                duration_ms,
+                size,
+                ..
+            } => CrateStatus {
+                status: build_status.clone(),
+                duration_ms: Some(*duration_ms),
+                size: size.clone(),
+                diagnostics: Vec::new(),
+                ..status(name)
+            },
+            Event::BuildFailed {
+                name,
+                duration_ms,
+                diagnostics,
+                ..
+            } => CrateStatus {
+                status: "failed".into(),
+                duration_ms: Some(*duration_ms),
+                diagnostics: diagnostics.clone(),
+                ..status(name)
+            },
+            Event::BuildCancelled { name, .. } => CrateStatus {
+                status: "cancelled".into(),
+                ..status(name)
+            },
+            _ => return,
+        };
+        crates.insert(next.name.clone(), next);
+    }
+
+    // push the event to the subscribers, the disconnected ones are dropped
+    fn publish(&self, event: &Event) {
+        let notification = json!({ "jsonrpc": "2.0", "method": "event", "params": event });
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|client| send(client, &notification).is_ok());
+    }
+
+    fn unsubscribe(&self, client: &Client) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|i| !Arc::ptr_eq(i, client));
+    }
+
+    // stop the running builds, then the socket is gone and the watch exits
+    fn shutdown(&self) {
+        BuildCancel::cancel_all(Duration::from_secs(5));
+        if let Some(path) = &self.socket {
+            let _ = std::fs::remove_file(path);
+        }
+        if self.requests.send(WatchRequest::Quit).is_err() {
+            std::process::exit(0);
+        }
+    }
+}
+
+fn send(client: &Client, message: &Value) -> io::Result<()> {
+    let mut writer = client.lock().unwrap();
+    writeln!(writer, "{}", message)?;
+    writer.flush()
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod daemon_tests {
+    use super::*;
+    use crate::config::RswConfig;
+    use crate::core::{BuildResult, BuildStatus};
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    fn config(content: &str) -> Arc<RswConfig> {
+        Arc::new(toml::from_str(content).unwrap())
+    }
+
+    fn daemon() -> (Daemon, std::sync::mpsc::Receiver<WatchRequest>, Client) {
+        let config = SharedConfig::new(config("[[crates]]\nname = \"foo\""));
+        let (tx, rx) = channel();
+        let client: Client = Arc::new(Mutex::new(Box::new(io::sink())));
+        (Daemon::new(config, tx, None), rx, client)
+    }
+
+    #[test]
+    fn status_follows_events() {
+        let (daemon, _, client) = daemon();
+        daemon.update(&Event::BuildStarted {
+            name: "foo".into(),
+            mode: "watch".into(),
+        });
+        let (response, _) = daemon.handle(r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#, &client);
+        let response = response.unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["crates"][0]["status"], "building");
+
+        let result = BuildResult {
+            name: "foo".into(),
+            status: BuildStatus::Failed,
+            duration: Duration::from_millis(20),
+            size: None,
+            diagnostics: vec![],
+        };
+        daemon.update(&Event::build_result(&result, "watch").unwrap());
+        let crates = daemon.crates.lock().unwrap();
+        assert_eq!(crates["foo"].status, "failed");
+        assert_eq!(crates["foo"].duration_ms, Some(20));
+    }
+
+    #[test]
+    fn build_requests() {
+        let (daemon, rx, client) = daemon();
+        let (response, _) = daemon.handle(
+            r#"{"jsonrpc":"2.0","id":2,"method":"build","params":{"crate":"foo"}}"#,
+            &client,
+        );
+        assert_eq!(response.unwrap()["result"]["crates"][0], "foo");
+        assert_eq!(rx.try_recv(), Ok(WatchRequest::Build(vec!["foo".into()])));
+
+        let (response, _) = daemon.handle(
+            r#"{"jsonrpc":"2.0","id":3,"method":"build","params":{"crate":"bar"}}"#,
+            &client,
+        );
+        assert_eq!(response.unwrap()["error"]["code"], INVALID_PARAMS);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn build_follows_config() {
+        let (daemon, rx, client) = daemon();
+        daemon.config.set(config(
+            "[[crates]]\nname = \"foo\"\n[[crates]]\nname = \"bar\"",
+        ));
+        let (response, _) = daemon.handle(
+            r#"{"jsonrpc":"2.0","id":2,"method":"build","params":{"crate":"bar"}}"#,
+            &client,
+        );
+        assert_eq!(response.unwrap()["result"]["crates"][0], "bar");
+        assert_eq!(rx.try_recv(), Ok(WatchRequest::Build(vec!["bar".into()])));
+
+        daemon.config.set(config("[[crates]]\nname = \"bar\""));
+        let (response, _) = daemon.handle(
+            r#"{"jsonrpc":"2.0","id":3,"method":"build","params":{"crate":"foo"}}"#,
+            &client,
+        );
+        assert_eq!(response.unwrap()["error"]["code"], INVALID_PARAMS);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let (daemon, _, client) = daemon();
+        let (response, _) = daemon.handle("{", &client);
+        assert_eq!(response.unwrap()["error"]["code"], PARSE_ERROR);
+
+        let (response, _) = daemon.handle(r#"{"jsonrpc":"2.0","id":4,"method":"foo"}"#, &client);
+        assert_eq!(response.unwrap()["error"]["code"], METHOD_NOT_FOUND);
+
+        // notifications have no response
+        let (response, is_shutdown) =
+            daemon.handle(r#"{"jsonrpc":"2.0","method":"shutdown"}"#, &client);
+        assert!(response.is_none());
+        assert!(is_shutdown);
+    }
+
+    #[test]
+    fn shutdown_quits_watch() {
+        let (daemon, rx, _) = daemon();
+        daemon.shutdown();
+        assert_eq!(rx.try_recv(), Ok(WatchRequest::Quit));
+    }
+}
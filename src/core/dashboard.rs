@@ -0,0 +1,493 @@
+//! rsw watch --tui
+//!
+//! One row per crate instead of the scrolling build output.
+
+use colored::{ColoredString, Colorize};
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::io::{self, Write};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use crate::core::{
+    format_size, BuildResult, BuildStatus, Diagnostic, Event, SharedConfig, WatchRequest,
+};
+
+static KEYS: &str = "↑/↓ select  r rebuild  a rebuild all  p dev/profiling  space pause  q quit";
+
+// what the dashboard reacts to
+enum Message {
+    Event(Event),
+    Key(KeyEvent),
+}
+
+/// A crate on the dashboard
+#[derive(Debug, Clone, PartialEq)]
+struct Row {
+    name: String,
+    /// `idle` | `building` | `ok` | `failed`
+    status: &'static str,
+    /// the watch profile, switched between `dev` and `profiling`
+    profile: String,
+    started: Option<Instant>,
+    finished: Option<Instant>,
+    duration: Option<Duration>,
+    wasm: Option<u64>,
+    /// the first error of the last failed build
+    error: Option<String>,
+}
+
+impl Row {
+    fn new(name: &str, profile: &str) -> Row {
+        Row {
+            name: name.into(),
+            status: "idle",
+            profile: profile.into(),
+            started: None,
+            finished: None,
+            duration: None,
+            wasm: None,
+            error: None,
+        }
+    }
+
+    // the result of the initial build
+    fn init(&mut self, result: &BuildResult) {
+        self.status = match result.status {
+            BuildStatus::Ok | BuildStatus::UpToDate => "ok",
+            BuildStatus::Failed => "failed",
+            BuildStatus::Skipped | BuildStatus::Cancelled => return,
+        };
+        self.finished = Some(Instant::now());
+        self.duration = Some(result.duration);
+        self.wasm = result.size.as_ref().map(|s| s.wasm.raw);
+        if result.status == BuildStatus::Failed {
+            self.error = Some(excerpt(&result.diagnostics));
+        }
+    }
+
+    fn update(&mut self, event: &Event) {
+        match event {
+            Event::BuildStarted { .. } => {
+                self.status = "building";
+                self.started = Some(Instant::now());
+            }
+            Event::BuildFinished {
+                duration_ms, size, ..
+            } => {
+                self.status = "ok";
+                self.finished = Some(Instant::now());
+                self.duration = Some(Duration::from_millis(*duration_ms as u64));
+                self.wasm = size.as_ref().map(|s| s.wasm.raw);
+                self.error = None;
+            }
+            Event::BuildFailed {
+                duration_ms,
+                diagnostics,
+                ..
+            } => {
+                self.status = "failed";
+                self.finished = Some(Instant::now());
+                self.duration = Some(Duration::from_millis(*duration_ms as u64));
+                self.error = Some(excerpt(diagnostics));
+            }
+            Event::BuildCancelled { .. } => self.status = "idle",
+            _ => (),
+        }
+    }
+
+    fn render(&self, name_width: usize, width: usize) -> String {
+        let status = format!("{:<8}", self.status);
+        let status: ColoredString = match self.status {
+            "building" => status.yellow(),
+            "ok" => status.green(),
+            "failed" => status.red(),
+            _ => status.normal(),
+        };
+        let last_build = match self.finished {
+            Some(finished) => ago(finished.elapsed()),
+            None => "-".into(),
+        };
+        let duration = match (self.status, self.started, self.duration) {
+            ("building", Some(started), _) => format!("{:.1}s", started.elapsed().as_secs_f64()),
+            (_, _, Some(duration)) => format!("{:.2}s", duration.as_secs_f64()),
+            _ => "-".into(),
+        };
+        let wasm = self.wasm.map(format_size).unwrap_or_else(|| "-".into());
+        let name = format!("{:<name_width$}", self.name);
+        let rest = format!(
+            "  {:<9}  {:>10}  {:>8}  {:>10}  {}",
+            self.profile,
+            last_build,
+            duration,
+            wasm,
+            self.error.as_deref().unwrap_or_default()
+        );
+        // the status is colored, the rest is cut to the terminal width
+        let rest_width = width.saturating_sub(name_width + 2 + 8);
+        format!(
+            "{}  {}{}",
+            name.purple(),
+            status,
+            rest.chars().take(rest_width).collect::<String>()
+        )
+    }
+}
+
+pub struct Dashboard {
+    rows: Vec<Row>,
+    selected: usize,
+    paused: bool,
+    /// the config of the watch, the rows follow its reloads
+    config: SharedConfig,
+    requests: Sender<WatchRequest>,
+    events: Receiver<Event>,
+}
+
+impl Dashboard {
+    /// The crates of `watch`, with the results of the initial build.
+    /// Events are collected from now on.
+    pub fn new(
+        config: SharedConfig,
+        results: &[BuildResult],
+        requests: Sender<WatchRequest>,
+    ) -> Dashboard {
+        let mut dashboard = Dashboard {
+            rows: Vec::new(),
+            selected: 0,
+            paused: false,
+            config,
+            requests,
+            events: Event::subscribe(),
+        };
+        dashboard.sync();
+        for row in &mut dashboard.rows {
+            if let Some(result) = results.iter().find(|r| r.name == row.name) {
+                row.init(result);
+            }
+        }
+        dashboard
+    }
+
+    // one row for each watched crate of the current config,
+    // with the profile the watch builds it with
+    fn sync(&mut self) {
+        let config = self.config.get();
+        let mut rows = std::mem::take(&mut self.rows);
+        self.rows = config
+            .crates
+            .iter()
+            .filter(|i| i.watch.as_ref().unwrap().run.unwrap())
+            .map(|i| {
+                let profile = i.watch.as_ref().unwrap().profile.as_ref().unwrap();
+                let mut row = match rows.iter().position(|r| r.name == i.name) {
+                    Some(idx) => rows.swap_remove(idx),
+                    None => Row::new(&i.name, profile),
+                };
+                row.profile = profile.clone();
+                row
+            })
+            .collect();
+        self.selected = self.selected.min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Take over the terminal once watching has started
+    pub fn init(mut self) {
+        std::thread::spawn(move || {
+            while let Ok(event) = self.events.recv() {
+                if let Event::WatchStarted { .. } = event {
+                    break;
+                }
+                self.update(&event);
+            }
+            if let Err(e) = self.run() {
+                leave();
+                warn!("dashboard: {}", e);
+            }
+        });
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        enter()?;
+        let (tx, rx) = channel();
+        let events = std::mem::replace(&mut self.events, channel().1);
+        let event_tx = tx.clone();
+        std::thread::spawn(move || {
+            for event in events {
+                if event_tx.send(Message::Event(event)).is_err() {
+                    break;
+                }
+            }
+        });
+        std::thread::spawn(move || loop {
+            match event::read() {
+                Ok(TermEvent::Key(key)) if key.kind != KeyEventKind::Release => {
+                    if tx.send(Message::Key(key)).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => (),
+                Err(_) => break,
+            }
+        });
+
+        let mut stdout = io::stdout();
+        loop {
+            self.sync();
+            self.render(&mut stdout)?;
+            // redraw every second for the elapsed times
+            match rx.recv_timeout(Duration::from_secs(1)) {
+                Ok(Message::Event(event)) => self.update(&event),
+                Ok(Message::Key(key)) => {
+                    if !self.key(key) {
+                        leave();
+                        let _ = self.requests.send(WatchRequest::Quit);
+                        return Ok(());
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+
+    fn update(&mut self, event: &Event) {
+        let name = match event {
+            Event::BuildStarted { name, .. }
+            | Event::BuildFinished { name, .. }
+            | Event::BuildFailed { name, .. }
+            | Event::BuildCancelled { name, .. } => name,
+            _ => return,
+        };
+        // crates added to `rsw.toml` while watching
+        if !self.rows.iter().any(|r| r.name == *name) {
+            self.sync();
+        }
+        if let Some(row) = self.rows.iter_mut().find(|r| r.name == *name) {
+            row.update(event);
+        }
+    }
+
+    // `false` to quit
+    fn key(&mut self, key: KeyEvent) -> bool {
+        let selected = self.rows.get(self.selected).map(|r| r.name.clone());
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.rows.len().saturating_sub(1));
+            }
+            KeyCode::Char('r') | KeyCode::Enter => {
+                if let Some(name) = selected {
+                    let _ = self.requests.send(WatchRequest::Build(vec![name]));
+                }
+            }
+            KeyCode::Char('a') => {
+                let _ = self.requests.send(WatchRequest::Build(Vec::new()));
+            }
+            // the row shows the new profile once the watch has switched to it
+            KeyCode::Char('p') => {
+                if let Some(row) = self.rows.get(self.selected) {
+                    let profile = match row.profile.as_str() {
+                        "profiling" => "dev",
+                        _ => "profiling",
+                    };
+                    let request = WatchRequest::Profile(row.name.clone(), profile.into());
+                    let _ = self.requests.send(request);
+                }
+            }
+            KeyCode::Char(' ') => {
+                self.paused = !self.paused;
+                let _ = self.requests.send(WatchRequest::Pause(self.paused));
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn render(&self, out: &mut impl Write) -> io::Result<()> {
+        let width = terminal::size().map(|(w, _)| w as usize).unwrap_or(80);
+        let name_width = self
+            .rows
+            .iter()
+            .map(|r| r.name.len())
+            .chain(["crate".len()])
+            .max()
+            .unwrap();
+
+        let mut title = format!("rsw watch · {} crates", self.rows.len()).bold();
+        if self.paused {
+            title = format!("{} · paused", title).yellow().bold();
+        }
+        let header = format!(
+            "  {:<name_width$}  {:<8}  {:<9}  {:>10}  {:>8}  {:>10}  {}",
+            "crate", "status", "profile", "last build", "time", "wasm", "error"
+        );
+        let mut lines = vec![
+            title.to_string(),
+            String::new(),
+            header
+                .chars()
+                .take(width)
+                .collect::<String>()
+                .bold()
+                .to_string(),
+        ];
+        for (idx, row) in self.rows.iter().enumerate() {
+            let marker = if idx == self.selected { "›" } else { " " };
+            lines.push(format!(
+                "{} {}",
+                marker.cyan(),
+                row.render(name_width, width.saturating_sub(2))
+            ));
+        }
+        lines.push(String::new());
+        lines.push(KEYS.dimmed().to_string());
+
+        for (y, line) in lines.iter().enumerate() {
+            queue!(
+                out,
+                MoveTo(0, y as u16),
+                Clear(ClearType::CurrentLine),
+                Print(line)
+            )?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+}
+
+// the alternate screen, with the build output hidden
+fn enter() -> io::Result<()> {
+    Event::hide_output(true);
+    log::set_max_level(log::LevelFilter::Off);
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        leave();
+        hook(info);
+    }));
+    terminal::enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, Hide)
+}
+
+fn leave() {
+    let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    Event::hide_output(false);
+}
+
+// `12s ago`, `5m ago`, `2h ago`
+fn ago(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        _ => format!("{}h ago", secs / 3600),
+    }
+}
+
+// the first error: `src/lib.rs:3:5 mismatched types`
+fn excerpt(diagnostics: &[Diagnostic]) -> String {
+    let error = diagnostics
+        .iter()
+        .find(|d| d.level == "error")
+        .or_else(|| diagnostics.first());
+    match error {
+        Some(Diagnostic {
+            file: Some(file),
+            line: Some(line),
+            column: Some(column),
+            message,
+            ..
+        }) => format!("{}:{}:{} {}", file, line, column, message),
+        Some(diagnostic) => diagnostic.message.clone(),
+        None => "see .rsw/rsw.err".into(),
+    }
+}
+
+#[cfg(test)]
+mod dashboard_tests {
+    use super::*;
+    use crate::config::RswConfig;
+    use std::sync::Arc;
+
+    fn diagnostic(level: &str, message: &str) -> Diagnostic {
+        Diagnostic {
+            file: Some("src/lib.rs".into()),
+            line: Some(3),
+            column: Some(5),
+            level: level.into(),
+            code: None,
+            message: message.into(),
+            rendered: None,
+        }
+    }
+
+    #[test]
+    fn row_follows_events() {
+        let mut row = Row::new("foo", "dev");
+        row.update(&Event::BuildStarted {
+            name: "foo".into(),
+            mode: "watch".into(),
+        });
+        assert_eq!(row.status, "building");
+
+        row.update(&Event::BuildFailed {
+            name: "foo".into(),
+            mode: "watch".into(),
+            duration_ms: 1200,
+            diagnostics: vec![
+                diagnostic("warning", "unused variable"),
+                diagnostic("error", "mismatched types"),
+            ],
+        });
+        assert_eq!(row.status, "failed");
+        assert_eq!(row.duration, Some(Duration::from_millis(1200)));
+        assert_eq!(
+            row.error.as_deref(),
+            Some("src/lib.rs:3:5 mismatched types")
+        );
+
+        row.update(&Event::BuildCancelled {
+            name: "foo".into(),
+            mode: "watch".into(),
+        });
+        assert_eq!(row.status, "idle");
+    }
+
+    #[test]
+    fn rows_follow_config() {
+        let config = |content: &str| Arc::new(toml::from_str::<RswConfig>(content).unwrap());
+        let shared = SharedConfig::new(config(
+            "[[crates]]\nname = \"foo\"\n[crates.watch]\nprofile = \"profiling\"",
+        ));
+        let (tx, _rx) = channel();
+        let mut dashboard = Dashboard::new(shared.clone(), &[], tx);
+        assert_eq!(dashboard.rows, vec![Row::new("foo", "profiling")]);
+
+        dashboard.rows[0].status = "ok";
+        shared.set(config(
+            "[[crates]]\nname = \"foo\"\n[[crates]]\nname = \"bar\"",
+        ));
+        dashboard.sync();
+        let rows: Vec<_> = dashboard
+            .rows
+            .iter()
+            .map(|r| (r.name.as_str(), r.status, r.profile.as_str()))
+            .collect();
+        assert_eq!(rows, vec![("foo", "ok", "dev"), ("bar", "idle", "dev")]);
+    }
+
+    #[test]
+    fn elapsed_time() {
+        assert_eq!(ago(Duration::from_secs(12)), "12s ago");
+        assert_eq!(ago(Duration::from_secs(300)), "5m ago");
+        assert_eq!(ago(Duration::from_secs(7200)), "2h ago");
+    }
+}
@@ -0,0 +1,341 @@
+//! crate dependencies
+
+use globset::{Glob, GlobBuilder};
+use path_clean::PathClean;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+use crate::config::CrateConfig;
+use crate::core::RswErr;
+use crate::utils::{get_crate_metadata, get_root};
+
+// `[dependencies]`, `[build-dependencies]` and `[target.'cfg(..)'.dependencies]`
+static DEPS_TABLES: [&str; 2] = ["dependencies", "build-dependencies"];
+
+/// Local path dependencies declared in the `Cargo.toml` of the crate,
+/// resolved to absolute paths.
+pub fn path_deps(name: &str, crate_root: &Path) -> Vec<PathBuf> {
+    let metadata = get_crate_metadata(name, crate_root.to_path_buf());
+    manifest_path_deps(&metadata, crate_root)
+}
+
+/// All local path dependencies of the crate, including indirect ones.
+/// Crates whose `Cargo.toml` cannot be read are skipped.
+pub fn local_deps(crate_root: &Path) -> Vec<PathBuf> {
+    let mut deps: Vec<PathBuf> = Vec::new();
+    let mut pending = vec![crate_root.to_path_buf()];
+
+    while let Some(root) = pending.pop() {
+        let metadata = match read_manifest(&root) {
+            Some(metadata) => metadata,
+            None => continue,
+        };
+        for dep in manifest_path_deps(&metadata, &root) {
+            if dep != crate_root && !deps.contains(&dep) {
+                deps.push(dep.clone());
+                pending.push(dep);
+            }
+        }
+    }
+
+    deps
+}
+
+/// The files and directories whose changes affect the build of the crate:
+/// `src`, `Cargo.toml` and `build.rs` of the crate and its local dependencies,
+/// the workspace `Cargo.toml` and `Cargo.lock`.
+pub fn input_paths(crate_root: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let roots = std::iter::once(crate_root.to_path_buf()).chain(local_deps(crate_root));
+    for root in roots {
+        paths.push(root.join("src"));
+        paths.push(root.join("Cargo.toml"));
+        if root.join("build.rs").is_file() {
+            paths.push(root.join("build.rs"));
+        }
+    }
+    if let Some(root) = workspace_root(crate_root) {
+        paths.push(root.join("Cargo.toml"));
+    }
+    if let Some(lock) = cargo_lock(crate_root) {
+        paths.push(lock);
+    }
+
+    let mut unique: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        if !unique.contains(&path) {
+            unique.push(path);
+        }
+    }
+    unique
+}
+
+/// A `[crates.watch] paths` pattern of the crate as an absolute glob,
+/// with the directory its files are in
+pub fn watch_glob(crate_root: &Path, pattern: &str) -> Result<(Glob, PathBuf), globset::Error> {
+    let path = crate_root.join(pattern).clean();
+    let glob = GlobBuilder::new(&path.to_string_lossy())
+        .literal_separator(true)
+        .build()?;
+    Ok((glob, glob_base(&path)))
+}
+
+// the directory of a glob without the wildcard components, e.g. `assets` for `assets/**/*.txt`
+fn glob_base(glob: &Path) -> PathBuf {
+    glob.components()
+        .take_while(|c| {
+            !c.as_os_str()
+                .to_string_lossy()
+                .contains(['*', '?', '[', '{'])
+        })
+        .collect()
+}
+
+/// The nearest directory, the crate root included, whose `Cargo.toml` has a `[workspace]`
+pub fn workspace_root(crate_root: &Path) -> Option<PathBuf> {
+    crate_root
+        .to_path_buf()
+        .clean()
+        .ancestors()
+        .find(|dir| read_manifest(dir).is_some_and(|m| m.get("workspace").is_some()))
+        .map(Path::to_path_buf)
+}
+
+/// The `Cargo.lock` of the crate or of its workspace
+pub fn cargo_lock(crate_root: &Path) -> Option<PathBuf> {
+    crate_root
+        .to_path_buf()
+        .clean()
+        .ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|lock| lock.is_file())
+}
+
+fn read_manifest(root: &Path) -> Option<Value> {
+    let content = fs::read_to_string(root.join("Cargo.toml")).ok()?;
+    content.parse::<Value>().ok()
+}
+
+// `foo = { workspace = true }`, the path of `foo` in `[workspace.dependencies]`
+fn workspace_dep_path(crate_root: &Path, name: &str) -> Option<PathBuf> {
+    let root = workspace_root(crate_root)?;
+    let metadata = read_manifest(&root)?;
+    let dep = metadata.get("workspace")?.get("dependencies")?.get(name)?;
+    let path = dep.get("path")?.as_str()?;
+    Some(root.join(path).clean())
+}
+
+fn manifest_path_deps(metadata: &Value, crate_root: &Path) -> Vec<PathBuf> {
+    let mut tables = Vec::new();
+
+    for key in DEPS_TABLES {
+        tables.push(metadata.get(key));
+    }
+    if let Some(Value::Table(targets)) = metadata.get("target") {
+        for target in targets.values() {
+            for key in DEPS_TABLES {
+                tables.push(target.get(key));
+            }
+        }
+    }
+
+    let mut deps = Vec::new();
+    for table in tables.into_iter().flatten() {
+        if let Value::Table(table) = table {
+            for (name, dep) in table {
+                let dep_root = match dep.get("path").and_then(|p| p.as_str()) {
+                    Some(path) => crate_root.join(path).clean(),
+                    None if dep.get("workspace").and_then(|w| w.as_bool()) == Some(true) => {
+                        match workspace_dep_path(crate_root, name) {
+                            Some(dep_root) => dep_root,
+                            None => continue,
+                        }
+                    }
+                    None => continue,
+                };
+                if !deps.contains(&dep_root) {
+                    deps.push(dep_root);
+                }
+            }
+        }
+    }
+
+    deps
+}
+
+/// Absolute path of the crate: `<cwd>/<root>/<name>`
+pub fn crate_root(config: &CrateConfig) -> PathBuf {
+    get_root()
+        .join(config.root.as_deref().unwrap_or("."))
+        .join(&config.name)
+        .clean()
+}
+
+/// Dependency graph of the crates configured in `rsw.toml`,
+/// nodes are indices into the crate list.
+#[derive(Debug)]
+pub struct DepGraph {
+    deps: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+}
+
+impl DepGraph {
+    pub fn new(crates: &[CrateConfig]) -> Result<DepGraph, RswErr> {
+        let roots: Vec<PathBuf> = crates.iter().map(crate_root).collect();
+        let mut edges = Vec::new();
+
+        for (idx, i) in crates.iter().enumerate() {
+            let deps = path_deps(&i.name, &roots[idx])
+                .iter()
+                .filter_map(|dep| roots.iter().position(|r| r == dep))
+                .filter(|dep| *dep != idx)
+                .collect();
+            edges.push(deps);
+        }
+
+        let graph = DepGraph::from_edges(edges);
+        if let Err(cycle) = graph.sort() {
+            let names = cycle.iter().map(|i| crates[*i].name.clone()).collect();
+            return Err(RswErr::CrateCycle(names));
+        }
+
+        Ok(graph)
+    }
+
+    /// `edges[i]` - the nodes that node `i` depends on
+    pub fn from_edges(edges: Vec<Vec<usize>>) -> DepGraph {
+        let mut dependents = vec![Vec::new(); edges.len()];
+        for (idx, deps) in edges.iter().enumerate() {
+            for dep in deps {
+                dependents[*dep].push(idx);
+            }
+        }
+
+        DepGraph {
+            deps: edges,
+            dependents,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deps.is_empty()
+    }
+
+    pub fn deps(&self, idx: usize) -> &[usize] {
+        &self.deps[idx]
+    }
+
+    pub fn dependents(&self, idx: usize) -> &[usize] {
+        &self.dependents[idx]
+    }
+
+    /// Topological order, dependencies first.
+    /// Returns the nodes of a cycle if there is one.
+    pub fn sort(&self) -> Result<Vec<usize>, Vec<usize>> {
+        let mut remaining: Vec<usize> = self.deps.iter().map(|d| d.len()).collect();
+        let mut ready: Vec<usize> = (0..self.len()).filter(|i| remaining[*i] == 0).collect();
+        let mut order = Vec::new();
+
+        while !ready.is_empty() {
+            let idx = ready.remove(0);
+            order.push(idx);
+            for dependent in &self.dependents[idx] {
+                remaining[*dependent] -= 1;
+                if remaining[*dependent] == 0 {
+                    ready.push(*dependent);
+                }
+            }
+        }
+
+        if order.len() == self.len() {
+            return Ok(order);
+        }
+
+        // walk the unresolved nodes until one repeats
+        let mut path: Vec<usize> = Vec::new();
+        let mut node = (0..self.len()).find(|i| remaining[*i] > 0).unwrap();
+        while !path.contains(&node) {
+            path.push(node);
+            node = *self.deps[node].iter().find(|d| remaining[**d] > 0).unwrap();
+        }
+        let start = path.iter().position(|i| *i == node).unwrap();
+        let mut cycle = path.split_off(start);
+        cycle.push(node);
+
+        Err(cycle)
+    }
+}
+
+#[cfg(test)]
+mod dep_graph_tests {
+    use super::*;
+
+    #[test]
+    fn sort_deps_first() {
+        // 0 -> 1 -> 2
+        let graph = DepGraph::from_edges(vec![vec![1], vec![2], vec![]]);
+        assert_eq!(graph.sort(), Ok(vec![2, 1, 0]));
+    }
+
+    #[test]
+    fn sort_keeps_declaration_order() {
+        let graph = DepGraph::from_edges(vec![vec![], vec![], vec![0]]);
+        assert_eq!(graph.sort(), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn sort_cycle() {
+        // 0 -> 1 -> 2 -> 1
+        let graph = DepGraph::from_edges(vec![vec![1], vec![2], vec![1]]);
+        assert_eq!(graph.sort(), Err(vec![1, 2, 1]));
+    }
+}
+
+#[cfg(test)]
+mod input_paths_tests {
+    use super::*;
+
+    #[test]
+    fn workspace_dependency() {
+        let ws = std::env::temp_dir().join("rsw_input_paths_tests");
+        let _ = fs::remove_dir_all(&ws);
+        fs::create_dir_all(ws.join("app/src")).unwrap();
+        fs::create_dir_all(ws.join("shared/src")).unwrap();
+        fs::write(
+            ws.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\", \"shared\"]\n[workspace.dependencies]\nshared = { path = \"shared\" }\n",
+        )
+        .unwrap();
+        fs::write(ws.join("Cargo.lock"), "").unwrap();
+        fs::write(
+            ws.join("app/Cargo.toml"),
+            "[package]\nname = \"app\"\n[dependencies]\nshared = { workspace = true }\n",
+        )
+        .unwrap();
+        fs::write(
+            ws.join("shared/Cargo.toml"),
+            "[package]\nname = \"shared\"\n",
+        )
+        .unwrap();
+
+        let paths = input_paths(&ws.join("app"));
+        let _ = fs::remove_dir_all(&ws);
+
+        assert_eq!(
+            paths,
+            vec![
+                ws.join("app/src"),
+                ws.join("app/Cargo.toml"),
+                ws.join("shared/src"),
+                ws.join("shared/Cargo.toml"),
+                ws.join("Cargo.toml"),
+                ws.join("Cargo.lock"),
+            ]
+        );
+    }
+}
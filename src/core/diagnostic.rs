@@ -0,0 +1,131 @@
+//! rsw diagnostics
+//!
+//! cargo `--message-format=json-diagnostic-rendered-ansi` compiler messages,
+//! written to `.rsw/rsw.diagnostics.json`
+
+use anyhow::Result;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+
+use crate::config;
+use crate::utils::dot_rsw_dir;
+
+// parallel builds update the same file
+static DIAGNOSTICS_LOCK: Mutex<()> = Mutex::new(());
+
+/// A compiler message, located at its primary span
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+    /// `error` | `warning` | `note` | `help` ...
+    pub level: String,
+    /// error code, e.g. `E0308`
+    pub code: Option<String>,
+    pub message: String,
+    /// the message as printed by rustc, without colors
+    pub rendered: Option<String>,
+}
+
+/// `.rsw/rsw.diagnostics.json`
+#[derive(Debug, Serialize, Deserialize)]
+struct DiagnosticsFile {
+    version: u32,
+    /// failed crates, by npm package name
+    crates: BTreeMap<String, Vec<Diagnostic>>,
+}
+
+impl Diagnostic {
+    /// Parse a line of `cargo build --message-format=json-diagnostic-rendered-ansi` output,
+    /// only `compiler-message` lines are diagnostics. `rendered` keeps rustc's colors.
+    pub fn parse(line: &str) -> Option<Diagnostic> {
+        let json: Value = serde_json::from_str(line).ok()?;
+        if json["reason"] != "compiler-message" {
+            return None;
+        }
+        let message = &json["message"];
+        let span = message["spans"]
+            .as_array()
+            .and_then(|spans| spans.iter().find(|s| s["is_primary"] == true));
+
+        Some(Diagnostic {
+            file: span.and_then(|s| s["file_name"].as_str().map(Into::into)),
+            line: span.and_then(|s| s["line_start"].as_u64()),
+            column: span.and_then(|s| s["column_start"].as_u64()),
+            level: message["level"].as_str().unwrap_or_default().into(),
+            code: message["code"]["code"].as_str().map(Into::into),
+            message: message["message"].as_str().unwrap_or_default().into(),
+            rendered: message["rendered"].as_str().map(Into::into),
+        })
+    }
+
+    /// Record the diagnostics of a failed crate, an empty list clears the crate.
+    pub fn save(name: &str, diagnostics: &[Diagnostic]) -> Result<()> {
+        let _lock = DIAGNOSTICS_LOCK.lock().unwrap();
+        let path = dot_rsw_dir().join(config::RSW_DIAGNOSTICS);
+        let mut file = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or(DiagnosticsFile {
+                version: 1,
+                crates: BTreeMap::new(),
+            });
+
+        if diagnostics.is_empty() {
+            file.crates.remove(name);
+        } else {
+            file.crates.insert(name.into(), diagnostics.to_vec());
+        }
+
+        fs::create_dir_all(dot_rsw_dir())?;
+        fs::write(path, serde_json::to_string_pretty(&file)?)?;
+
+        Ok(())
+    }
+}
+
+/// The text without its ANSI color codes
+pub fn strip_ansi(text: &str) -> String {
+    let re = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+    re.replace_all(text, "").into()
+}
+
+#[cfg(test)]
+mod diagnostic_tests {
+    use super::*;
+
+    #[test]
+    fn parse_compiler_message() {
+        let line = r#"{"reason":"compiler-message","package_id":"foo 0.1.0","message":{"rendered":"error[E0308]: mismatched types\n","code":{"code":"E0308","explanation":null},"level":"error","message":"mismatched types","spans":[{"file_name":"src/lib.rs","line_start":3,"column_start":5,"is_primary":true}],"children":[]}}"#;
+        assert_eq!(
+            Diagnostic::parse(line),
+            Some(Diagnostic {
+                file: Some("src/lib.rs".into()),
+                line: Some(3),
+                column: Some(5),
+                level: "error".into(),
+                code: Some("E0308".into()),
+                message: "mismatched types".into(),
+                rendered: Some("error[E0308]: mismatched types\n".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_other_lines() {
+        assert_eq!(Diagnostic::parse(r#"{"reason":"build-finished"}"#), None);
+        assert_eq!(Diagnostic::parse("   Compiling foo v0.1.0"), None);
+    }
+
+    #[test]
+    fn strip_colors() {
+        assert_eq!(
+            strip_ansi("\x1b[0m\x1b[1m\x1b[38;5;9merror[E0308]\x1b[0m: mismatched types"),
+            "error[E0308]: mismatched types"
+        );
+    }
+}
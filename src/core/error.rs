@@ -3,10 +3,25 @@ use core::fmt::Display;
 
 pub enum RswErr {
     WasmPack,
+    WasmOpt,
     Config(std::io::Error),
     ParseToml(toml::de::Error),
     WatchFile(notify::Error),
     Crate(String, std::io::Error),
+    CrateCycle(Vec<String>),
+    Package(String, anyhow::Error),
+    SizeBudget(String, u64, u64),
+    SizeBudgetInvalid(String, String),
+    /// crate name, hook, command, exit status or spawn error
+    Hook(String, String, String, String),
+    /// crate name, timeout in seconds
+    BuildTimeout(String, u64),
+    /// crate name, pattern, error
+    WatchPattern(String, String, String),
+    /// address, error
+    Serve(String, std::io::Error),
+    /// socket path, error
+    Daemon(String, std::io::Error),
 }
 
 impl Display for RswErr {
@@ -19,6 +34,13 @@ impl Display for RswErr {
                     "https://github.com/rustwasm/wasm-pack".green(),
                 )
             }
+            RswErr::WasmOpt => {
+                write!(f,
+                    "{} wasm-opt {}\nCannot find wasm-opt in your PATH. Please make sure binaryen is installed, or remove `wasm-opt` from `rsw.toml`.",
+                    "[⚙️ rsw::env]".red().on_black(),
+                    "https://github.com/WebAssembly/binaryen".green(),
+                )
+            }
             RswErr::Config(_err) => {
                 write!(
                     f,
@@ -48,6 +70,90 @@ impl Display for RswErr {
                     err
                 )
             }
+            RswErr::Package(name, err) => {
+                write!(
+                    f,
+                    "{} {} combined package: {}",
+                    "[📦 rsw::package]".red().on_black(),
+                    name.yellow(),
+                    err
+                )
+            }
+            RswErr::SizeBudget(name, size, budget) => {
+                write!(
+                    f,
+                    "{} {} wasm size {} exceeds the size-budget {}",
+                    "[📦 rsw::size]".red().on_black(),
+                    name.yellow(),
+                    crate::core::format_size(*size).red(),
+                    crate::core::format_size(*budget),
+                )
+            }
+            RswErr::WatchPattern(name, pattern, err) => {
+                write!(
+                    f,
+                    "{} {} invalid watch pattern {:?}: {}",
+                    "[⚙️ rsw.toml]".red().on_black(),
+                    name.yellow(),
+                    pattern,
+                    err,
+                )
+            }
+            RswErr::Daemon(socket, err) => {
+                write!(
+                    f,
+                    "{} failed to listen on {}: {}",
+                    "[🛰 rsw::daemon]".red().on_black(),
+                    socket.yellow(),
+                    err,
+                )
+            }
+            RswErr::Serve(addr, err) => {
+                write!(
+                    f,
+                    "{} failed to listen on {}: {}",
+                    "[🌐 rsw::serve]".red().on_black(),
+                    addr.yellow(),
+                    err,
+                )
+            }
+            RswErr::BuildTimeout(name, secs) => {
+                write!(
+                    f,
+                    "{} {} build timed out after {}s",
+                    "[⏱ rsw::timeout]".red().on_black(),
+                    name.yellow(),
+                    secs,
+                )
+            }
+            RswErr::Hook(name, hook, command, reason) => {
+                write!(
+                    f,
+                    "{} {} {} `{}` failed: {}",
+                    "[🪝 rsw::hook]".red().on_black(),
+                    name.yellow(),
+                    hook,
+                    command,
+                    reason,
+                )
+            }
+            RswErr::SizeBudgetInvalid(name, budget) => {
+                write!(
+                    f,
+                    "{} {} invalid size-budget {:?}, e.g. \"200KB\", \"1.5MB\" or a number of bytes",
+                    "[⚙️ rsw.toml]".red().on_black(),
+                    name.yellow(),
+                    budget,
+                )
+            }
+            RswErr::CrateCycle(names) => {
+                write!(
+                    f,
+                    "{} dependency cycle between crates: {}",
+                    "[🦀 rsw::crate]".red().on_black(),
+                    names.join(" -> ").yellow()
+                )
+            }
         }
     }
 }
@@ -0,0 +1,176 @@
+//! build events
+//!
+//! `--message-format json` prints one JSON event per line on stdout,
+//! everything else goes to stderr. `rsw daemon` subscribes to them.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+
+use crate::core::{BuildResult, BuildStatus, Diagnostic, SizeReport};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+// stdout is used for machine readable output, the other output goes to stderr
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+// the terminal is used by the dashboard, the other output is dropped
+static OUTPUT_HIDDEN: AtomicBool = AtomicBool::new(false);
+static LISTENERS: Mutex<Vec<Sender<Event>>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// the watched crates, by npm package name
+    WatchStarted {
+        crates: Vec<String>,
+    },
+    /// the crates that use the changed file
+    FileChanged {
+        path: PathBuf,
+        crates: Vec<String>,
+    },
+    BuildStarted {
+        name: String,
+        mode: String,
+    },
+    /// `status`: `ok` | `up-to-date`
+    BuildFinished {
+        name: String,
+        mode: String,
+        status: String,
+        duration_ms: u128,
+        size: Option<SizeReport>,
+    },
+    BuildFailed {
+        name: String,
+        mode: String,
+        duration_ms: u128,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// stopped by a newer change in `watch` mode
+    BuildCancelled {
+        name: String,
+        mode: String,
+    },
+    /// `cli`: `npm link` | `yarn link` | `pnpm link`
+    Linked {
+        cli: String,
+        packages: Vec<String>,
+    },
+}
+
+impl Event {
+    pub fn set_json(json: bool) {
+        JSON.store(json, Ordering::SeqCst);
+        if json {
+            Event::reserve_stdout();
+        }
+    }
+
+    pub fn is_json() -> bool {
+        JSON.load(Ordering::SeqCst)
+    }
+
+    /// keep stdout for machine readable output, e.g. `rsw daemon --stdio`
+    pub fn reserve_stdout() {
+        STDOUT_RESERVED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stdout_reserved() -> bool {
+        STDOUT_RESERVED.load(Ordering::SeqCst)
+    }
+
+    /// drop the human readable output, e.g. while the dashboard is shown
+    pub fn hide_output(hidden: bool) {
+        OUTPUT_HIDDEN.store(hidden, Ordering::SeqCst);
+    }
+
+    pub fn is_output_hidden() -> bool {
+        OUTPUT_HIDDEN.load(Ordering::SeqCst)
+    }
+
+    /// Receive every event emitted from now on
+    pub fn subscribe() -> Receiver<Event> {
+        let (tx, rx) = channel();
+        LISTENERS.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// The event of a finished build, skipped crates have none
+    pub fn build_result(result: &BuildResult, mode: &str) -> Option<Event> {
+        let name = result.name.clone();
+        let mode = mode.to_string();
+        let duration_ms = result.duration.as_millis();
+        match result.status {
+            BuildStatus::Ok | BuildStatus::UpToDate => Some(Event::BuildFinished {
+                name,
+                mode,
+                status: result.status.as_str().into(),
+                duration_ms,
+                size: result.size.clone(),
+            }),
+            BuildStatus::Failed => Some(Event::BuildFailed {
+                name,
+                mode,
+                duration_ms,
+                diagnostics: result.diagnostics.clone(),
+            }),
+            BuildStatus::Cancelled => Some(Event::BuildCancelled { name, mode }),
+            BuildStatus::Skipped => None,
+        }
+    }
+
+    /// Send the event to the subscribers,
+    /// and print it as a line of JSON with `--message-format json`
+    pub fn emit(self) {
+        LISTENERS
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(self.clone()).is_ok());
+
+        if !Event::is_json() {
+            return;
+        }
+        if let Ok(line) = serde_json::to_string(&self) {
+            let mut stdout = std::io::stdout().lock();
+            let _ = writeln!(stdout, "{}", line);
+            let _ = stdout.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod event_tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn tagged_by_event() {
+        let event = Event::FileChanged {
+            path: PathBuf::from("foo/src/lib.rs"),
+            crates: vec!["foo".into()],
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"file-changed","path":"foo/src/lib.rs","crates":["foo"]}"#
+        );
+    }
+
+    #[test]
+    fn failed_build_result() {
+        let result = BuildResult {
+            name: "foo".into(),
+            status: BuildStatus::Failed,
+            duration: Duration::from_millis(1500),
+            size: None,
+            diagnostics: vec![],
+        };
+        let json = serde_json::to_value(Event::build_result(&result, "watch")).unwrap();
+        assert_eq!(json["event"], "build-failed");
+        assert_eq!(json["duration_ms"], 1500);
+
+        let skipped = BuildResult::skipped("foo");
+        assert!(Event::build_result(&skipped, "watch").is_none());
+    }
+}
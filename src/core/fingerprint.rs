@@ -0,0 +1,199 @@
+//! rsw fingerprint
+//!
+//! Skip `wasm-pack build` when nothing changed since the last successful build.
+
+use anyhow::Result;
+use globset::GlobMatcher;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::core::deps::{cargo_lock, local_deps, watch_glob, workspace_root};
+use crate::utils::{create_file, dot_rsw_dir, get_root};
+
+/// The inputs of a crate build: source files, `Cargo.toml`, `Cargo.lock`,
+/// the files matched by `[crates.watch] paths` and build options.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    inputs: BTreeMap<String, String>,
+}
+
+impl Fingerprint {
+    pub fn new(
+        crate_root: &Path,
+        profile: &str,
+        target: &str,
+        args: &[&str],
+        paths: &[String],
+    ) -> Fingerprint {
+        let mut inputs = BTreeMap::new();
+        inputs.insert("profile".into(), profile.into());
+        inputs.insert("target".into(), target.into());
+        inputs.insert("args".into(), args.join(" "));
+
+        let mut fingerprint = Fingerprint { inputs };
+        fingerprint.add_crate(crate_root);
+        for dep in local_deps(crate_root) {
+            fingerprint.add_crate(&dep);
+        }
+        if let Some(root) = workspace_root(crate_root) {
+            fingerprint.add_file(&root.join("Cargo.toml"));
+        }
+        if let Some(lock) = cargo_lock(crate_root) {
+            fingerprint.add_file(&lock);
+        }
+        for pattern in paths {
+            if let Ok((glob, dir)) = watch_glob(crate_root, pattern) {
+                fingerprint.add_glob(&dir, &glob.compile_matcher());
+            }
+        }
+
+        fingerprint
+    }
+
+    /// The fingerprint of the last successful build
+    pub fn load(name: &str) -> Option<Fingerprint> {
+        let content = fs::read_to_string(fingerprint_path(name)).ok()?;
+        let inputs = toml::from_str(&content).ok()?;
+        Some(Fingerprint { inputs })
+    }
+
+    pub fn save(&self, name: &str) -> Result<()> {
+        let content = toml::to_string(&self.inputs)?;
+        create_file(&fingerprint_path(name))?.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn remove(name: &str) {
+        let _ = fs::remove_file(fingerprint_path(name));
+    }
+
+    /// The inputs that differ between the two fingerprints
+    pub fn changes(&self, prev: &Fingerprint) -> Vec<String> {
+        let mut changes = Vec::new();
+        for (key, value) in &self.inputs {
+            match prev.inputs.get(key) {
+                Some(prev_value) if prev_value == value => {}
+                Some(_) => changes.push(format!("{} changed", key)),
+                None => changes.push(format!("{} added", key)),
+            }
+        }
+        for key in prev.inputs.keys() {
+            if !self.inputs.contains_key(key) {
+                changes.push(format!("{} removed", key));
+            }
+        }
+        changes
+    }
+
+    fn add_crate(&mut self, crate_root: &Path) {
+        self.add_file(&crate_root.join("Cargo.toml"));
+        self.add_file(&crate_root.join("build.rs"));
+        self.add_dir(&crate_root.join("src"));
+    }
+
+    fn add_dir(&mut self, dir: &Path) {
+        let mut entries = match fs::read_dir(dir) {
+            Ok(entries) => entries.flatten().map(|e| e.path()).collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                self.add_dir(&path);
+            } else {
+                self.add_file(&path);
+            }
+        }
+    }
+
+    // the files of `dir` matched by `glob`
+    fn add_glob(&mut self, dir: &Path, glob: &GlobMatcher) {
+        if dir.is_file() {
+            if glob.is_match(dir) {
+                self.add_file(dir);
+            }
+            return;
+        }
+        let mut entries = match fs::read_dir(dir) {
+            Ok(entries) => entries.flatten().map(|e| e.path()).collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        entries.sort();
+        for path in entries {
+            self.add_glob(&path, glob);
+        }
+    }
+
+    fn add_file(&mut self, path: &Path) {
+        if let Ok(content) = fs::read(path) {
+            let key = path.strip_prefix(get_root()).unwrap_or(path);
+            self.inputs.insert(
+                key.to_string_lossy().to_string(),
+                format!("{:x}", Sha256::digest(&content)),
+            );
+        }
+    }
+}
+
+// `.rsw/fingerprint/<name>.toml`, the scope separator of `@rsw/foo` is replaced
+fn fingerprint_path(name: &str) -> PathBuf {
+    dot_rsw_dir()
+        .join(config::RSW_FINGERPRINT)
+        .join(format!("{}.toml", name.replace('/', "__")))
+}
+
+#[cfg(test)]
+mod fingerprint_tests {
+    use super::*;
+
+    fn fingerprint(inputs: &[(&str, &str)]) -> Fingerprint {
+        Fingerprint {
+            inputs: inputs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn changes_none() {
+        let a = fingerprint(&[("profile", "dev"), ("src/lib.rs", "1")]);
+        let b = fingerprint(&[("profile", "dev"), ("src/lib.rs", "1")]);
+        assert!(a.changes(&b).is_empty());
+    }
+
+    #[test]
+    fn watch_paths() {
+        let root = std::env::temp_dir().join("rsw_fingerprint_tests");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("c/src")).unwrap();
+        fs::create_dir_all(root.join("c/assets/sub")).unwrap();
+        fs::write(root.join("c/Cargo.toml"), "[package]\nname = \"c\"\n").unwrap();
+        fs::write(root.join("c/assets/a.txt"), "a").unwrap();
+        fs::write(root.join("c/assets/b.bin"), "b").unwrap();
+        fs::write(root.join("c/assets/sub/c.txt"), "c").unwrap();
+
+        let paths = vec!["assets/*.txt".to_string()];
+        let fingerprint = Fingerprint::new(&root.join("c"), "dev", "web", &[], &paths);
+        let _ = fs::remove_dir_all(&root);
+
+        let key = |path: &str| root.join(path).to_string_lossy().to_string();
+        assert!(fingerprint.inputs.contains_key(&key("c/assets/a.txt")));
+        assert!(!fingerprint.inputs.contains_key(&key("c/assets/b.bin")));
+        assert!(!fingerprint.inputs.contains_key(&key("c/assets/sub/c.txt")));
+    }
+
+    #[test]
+    fn changes_inputs() {
+        let prev = fingerprint(&[("profile", "dev"), ("src/a.rs", "1"), ("src/b.rs", "1")]);
+        let next = fingerprint(&[("profile", "release"), ("src/a.rs", "1"), ("src/c.rs", "1")]);
+        assert_eq!(
+            next.changes(&prev),
+            vec!["profile changed", "src/c.rs added", "src/b.rs removed"]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! rsw hooks
+//!
+//! `pre-build`, `post-build` and `on-failure` commands of `rsw.toml`
+
+use crate::config::{CrateConfig, HooksOptions};
+use crate::core::deps::crate_root;
+use crate::core::RswErr;
+use crate::utils::{get_root, os_command, print};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    PreBuild,
+    PostBuild,
+    OnFailure,
+}
+
+impl HookStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookStage::PreBuild => "pre-build",
+            HookStage::PostBuild => "post-build",
+            HookStage::OnFailure => "on-failure",
+        }
+    }
+
+    fn command<'a>(&self, hooks: &'a HooksOptions) -> Option<&'a String> {
+        match self {
+            HookStage::PreBuild => hooks.pre_build.as_ref(),
+            HookStage::PostBuild => hooks.post_build.as_ref(),
+            HookStage::OnFailure => hooks.on_failure.as_ref(),
+        }
+    }
+}
+
+/// The global and crate hooks of a crate build
+pub struct Hooks {
+    name: String,
+    global: HooksOptions,
+    local: HooksOptions,
+    envs: Vec<(&'static str, String)>,
+}
+
+impl Hooks {
+    pub fn new(
+        global: &HooksOptions,
+        config: &CrateConfig,
+        profile: &str,
+        rsw_type: &str,
+    ) -> Hooks {
+        let root = crate_root(config);
+        let out_dir = root.join(config.link_dir());
+        let envs = vec![
+            ("RSW_CRATE_NAME", config.name.clone()),
+            ("RSW_CRATE_ROOT", root.to_string_lossy().to_string()),
+            ("RSW_OUT_DIR", out_dir.to_string_lossy().to_string()),
+            ("RSW_PROFILE", profile.into()),
+            ("RSW_MODE", rsw_type.into()),
+        ];
+
+        Hooks {
+            name: config.name.clone(),
+            global: global.clone(),
+            local: config.hooks.clone(),
+            envs,
+        }
+    }
+
+    /// Run the global command of `stage`, then the one of the crate.
+    /// Returns `false` once a command fails.
+    pub fn run(&self, stage: HookStage) -> bool {
+        let commands = [stage.command(&self.global), stage.command(&self.local)];
+        for command in commands.into_iter().flatten() {
+            info!("🪝  {} {}", stage.as_str(), command);
+            let reason = match hook_command(command).envs(self.envs.clone()).status() {
+                Ok(status) if status.success() => continue,
+                Ok(status) => status.to_string(),
+                Err(e) => e.to_string(),
+            };
+            print(RswErr::Hook(
+                self.name.clone(),
+                stage.as_str().into(),
+                command.into(),
+                reason,
+            ));
+            return false;
+        }
+
+        true
+    }
+}
+
+// a shell command line, run in the project root
+fn hook_command(command: &str) -> std::process::Command {
+    if cfg!(target_os = "windows") {
+        os_command(command.into(), vec![], get_root())
+    } else {
+        os_command("sh".into(), vec!["-c".into(), command.into()], get_root())
+    }
+}
@@ -2,6 +2,8 @@ use colored::Colorize;
 use core::fmt::Display;
 use std::fmt::Debug;
 
+use crate::core::{format_delta, format_size, BuildResult, BuildStatus, SizeReport};
+
 #[derive(Debug)]
 pub enum RswInfo {
     SplitLine,
@@ -11,7 +13,16 @@ pub enum RswInfo {
     CrateLink(String, String),
     CrateFail(String, String),
     CrateOk(String, String, String),
+    /// the failed dependency, `None` if `--fail-fast` stopped the build
+    CrateSkip(String, String, Option<String>),
+    CrateCancel(String, String),
+    CrateFresh(String, String),
+    CrateSize(String, SizeReport, Option<SizeReport>),
+    BuildSummary(Vec<BuildResult>),
     CrateChange(std::path::PathBuf),
+    ConfigReload(String),
+    Serve(String),
+    Daemon(String),
     CrateNewOk(String),
     CrateNewExist(String),
     ConfigNewDir(String, std::path::PathBuf),
@@ -57,6 +68,124 @@ impl Display for RswInfo {
                 let rsw_tip = format!("[💢 rsw::{}]", mode);
                 write!(f, "{} {}", rsw_tip.red().on_black(), name)
             }
+            RswInfo::CrateFresh(name, mode) => {
+                let rsw_tip = match *mode == "watch" {
+                    true => "[👀 rsw::watch]",
+                    false => "[✨ rsw::build]",
+                };
+                write!(
+                    f,
+                    "{} {} up to date, use {} to rebuild",
+                    rsw_tip.green().on_black(),
+                    name.purple(),
+                    "--force".yellow(),
+                )
+            }
+            RswInfo::CrateSize(name, now, prev) => {
+                let file_size = |kind: &str, raw: u64, gzip: u64, prev: Option<u64>| {
+                    let delta = match prev {
+                        Some(prev) => format!(" {}", format_delta(raw, prev).yellow()),
+                        None => String::new(),
+                    };
+                    format!(
+                        "{} {} (gzip {}){}",
+                        kind,
+                        format_size(raw),
+                        format_size(gzip),
+                        delta
+                    )
+                };
+                write!(
+                    f,
+                    "{} {} {}, {}",
+                    "[📦 rsw::size]".green().on_black(),
+                    name.purple(),
+                    file_size(
+                        "wasm",
+                        now.wasm.raw,
+                        now.wasm.gzip,
+                        prev.as_ref().map(|p| p.wasm.raw)
+                    ),
+                    file_size(
+                        "js",
+                        now.js.raw,
+                        now.js.gzip,
+                        prev.as_ref().map(|p| p.js.raw)
+                    ),
+                )
+            }
+            RswInfo::CrateSkip(name, mode, dep) => {
+                let rsw_tip = format!("[💢 rsw::{}]", mode);
+                match dep {
+                    Some(dep) => write!(
+                        f,
+                        "{} {} skipped, dependency {} failed",
+                        rsw_tip.red().on_black(),
+                        name,
+                        dep.yellow()
+                    ),
+                    None => write!(
+                        f,
+                        "{} {} skipped, {} after a failure",
+                        rsw_tip.red().on_black(),
+                        name,
+                        "--fail-fast".yellow()
+                    ),
+                }
+            }
+            RswInfo::BuildSummary(results) => {
+                let name_width = results
+                    .iter()
+                    .map(|r| r.name.len())
+                    .chain(["crate".len()])
+                    .max()
+                    .unwrap();
+                let header = format!(
+                    "{:<name_width$}  {:<10}  {:>8}  {:>10}",
+                    "crate", "status", "time", "wasm"
+                );
+                write!(
+                    f,
+                    "{}\n  {}",
+                    "[📋 rsw::summary]".green().on_black(),
+                    header.bold()
+                )?;
+                for r in results {
+                    let status = format!("{:<10}", r.status.as_str());
+                    let status = match r.status {
+                        BuildStatus::Ok => status.green(),
+                        BuildStatus::UpToDate => status.cyan(),
+                        BuildStatus::Failed => status.red(),
+                        BuildStatus::Skipped | BuildStatus::Cancelled => status.yellow(),
+                    };
+                    let time = match r.status {
+                        BuildStatus::Skipped => "-".into(),
+                        _ => format!("{:.2}s", r.duration.as_secs_f64()),
+                    };
+                    let wasm = match &r.size {
+                        Some(size) => format_size(size.wasm.raw),
+                        None => "-".into(),
+                    };
+                    write!(
+                        f,
+                        "\n  {}  {}  {:>8}  {:>10}",
+                        format!("{:<name_width$}", r.name).purple(),
+                        status,
+                        time,
+                        wasm
+                    )?;
+                }
+                Ok(())
+            }
+            RswInfo::CrateCancel(name, mode) => {
+                let rsw_tip = format!("[🛑 rsw::{}]", mode);
+                write!(
+                    f,
+                    "{} {} build cancelled",
+                    rsw_tip.yellow().on_black(),
+                    name
+                )
+            }
             RswInfo::SplitLine => {
                 write!(f, "\n{}\n", "◼◻".repeat(24).yellow())
             }
@@ -68,6 +197,30 @@ impl Display for RswInfo {
                     path.display(),
                 )
             }
+            RswInfo::ConfigReload(file) => {
+                write!(
+                    f,
+                    "{} {} reloaded",
+                    "[⚙️ rsw.toml]".green().on_black(),
+                    file.yellow(),
+                )
+            }
+            RswInfo::Daemon(socket) => {
+                write!(
+                    f,
+                    "{} listening on {}",
+                    "[🛰 rsw::daemon]".green().on_black(),
+                    socket.yellow(),
+                )
+            }
+            RswInfo::Serve(url) => {
+                write!(
+                    f,
+                    "{} serving at {}",
+                    "[🌐 rsw::serve]".green().on_black(),
+                    url.yellow(),
+                )
+            }
             RswInfo::RunWatch(name) => {
                 write!(
                     f,
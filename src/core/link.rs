@@ -2,7 +2,7 @@
 
 use std::path::PathBuf;
 
-use crate::core::RswInfo;
+use crate::core::{Event, RswInfo};
 use crate::utils::{get_root, os_cli, print};
 
 pub struct Link {
@@ -26,6 +26,11 @@ impl Link {
     pub fn npm_link(cli: String, crates: Vec<String>) {
         os_cli(cli, [&["link".into()], &crates[..]].concat(), get_root());
         print(RswInfo::CrateLink("npm link".into(), crates.join(" ")));
+        Event::Linked {
+            cli: "npm link".into(),
+            packages: crates,
+        }
+        .emit();
     }
 
     pub fn yarn_link(&self) {
@@ -45,6 +50,11 @@ impl Link {
             "yarn link".into(),
             self.name.to_string(),
         ));
+        Event::Linked {
+            cli: "yarn link".into(),
+            packages: vec![self.name.clone()],
+        }
+        .emit();
     }
 
     pub fn pnpm_link(&self) {
@@ -60,6 +70,11 @@ impl Link {
             "pnpm link".into(),
             self.name.to_string(),
         ));
+        Event::Linked {
+            cli: "pnpm link".into(),
+            packages: vec![self.name.clone()],
+        }
+        .emit();
     }
 
     pub fn unlink(cli: &String, crates: Vec<String>) {
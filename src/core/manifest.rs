@@ -0,0 +1,167 @@
+//! rsw manifest
+//!
+//! `.rsw/rsw.manifest.json` - the outputs and the last build status of every crate
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, CrateConfig};
+use crate::core::deps::crate_root;
+use crate::utils::dot_rsw_dir;
+
+pub static MANIFEST_VERSION: u32 = 1;
+
+// parallel builds update the same file
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// by npm package name
+    pub crates: BTreeMap<String, ManifestCrate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestCrate {
+    /// npm package name
+    pub name: String,
+    /// absolute path of the crate
+    pub root: String,
+    /// absolute path of the npm package, the directory that gets linked
+    pub out_dir: String,
+    pub targets: Vec<String>,
+    /// `dev` | `profiling` | `release`
+    pub profile: String,
+    /// `version` in `Cargo.toml`
+    pub version: Option<String>,
+    pub outputs: Vec<ManifestOutput>,
+    /// `ok` | `failed` | `up-to-date` | `skipped`
+    pub status: String,
+    /// unix timestamp of the build, in seconds
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestOutput {
+    pub target: String,
+    /// absolute path of the output directory
+    pub dir: String,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// absolute path
+    pub path: String,
+    pub sha256: String,
+}
+
+impl ManifestCrate {
+    pub fn new(config: &CrateConfig, rsw_type: &str, status: &str) -> ManifestCrate {
+        let root = crate_root(config);
+        let profile = match rsw_type == "watch" {
+            true => config.watch.as_ref().unwrap().profile.clone(),
+            false => config.build.as_ref().unwrap().profile.clone(),
+        };
+        let version = fs::read_to_string(root.join("Cargo.toml"))
+            .ok()
+            .and_then(|content| content.parse::<toml::Value>().ok())
+            .and_then(|metadata| {
+                metadata
+                    .get("package")?
+                    .get("version")?
+                    .as_str()
+                    .map(Into::into)
+            });
+        let outputs = config
+            .outputs()
+            .into_iter()
+            .map(|(target, dir)| {
+                let dir = root.join(dir);
+                let mut files = Vec::new();
+                collect_files(&dir, &mut files);
+                ManifestOutput {
+                    target,
+                    dir: dir.to_string_lossy().to_string(),
+                    files,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        ManifestCrate {
+            name: config.name.clone(),
+            root: root.to_string_lossy().to_string(),
+            out_dir: root.join(config.link_dir()).to_string_lossy().to_string(),
+            targets: outputs.iter().map(|o| o.target.clone()).collect(),
+            profile: profile.unwrap_or_default(),
+            version,
+            outputs,
+            status: status.into(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn load() -> Option<Manifest> {
+        let content = fs::read_to_string(dot_rsw_dir().join(config::RSW_MANIFEST)).ok()?;
+        serde_json::from_str(&content)
+            .ok()
+            .filter(|m: &Manifest| m.version == MANIFEST_VERSION)
+    }
+
+    /// Record the last build of a crate
+    pub fn update(entry: ManifestCrate) -> Result<()> {
+        Manifest::edit(|manifest| {
+            manifest.crates.insert(entry.name.clone(), entry);
+        })
+    }
+
+    /// Drop the crates that are no longer in `rsw.toml`
+    pub fn retain(names: &[String]) -> Result<()> {
+        Manifest::edit(|manifest| manifest.crates.retain(|name, _| names.contains(name)))
+    }
+
+    fn edit<F: FnOnce(&mut Manifest)>(f: F) -> Result<()> {
+        let _lock = MANIFEST_LOCK.lock().unwrap();
+        let mut manifest = Manifest::load().unwrap_or(Manifest {
+            version: MANIFEST_VERSION,
+            crates: BTreeMap::new(),
+        });
+        f(&mut manifest);
+
+        fs::create_dir_all(dot_rsw_dir())?;
+        fs::write(
+            dot_rsw_dir().join(config::RSW_MANIFEST),
+            serde_json::to_string_pretty(&manifest)?,
+        )?;
+
+        Ok(())
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<ManifestFile>) {
+    let mut entries = match fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().map(|e| e.path()).collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if let Ok(content) = fs::read(&path) {
+            files.push(ManifestFile {
+                path: path.to_string_lossy().to_string(),
+                sha256: format!("{:x}", Sha256::digest(&content)),
+            });
+        }
+    }
+}
@@ -4,18 +4,40 @@ mod build;
 mod clean;
 mod cli;
 mod create;
+mod daemon;
+mod dashboard;
+mod deps;
+mod diagnostic;
 mod error;
+mod event;
+mod fingerprint;
+mod hook;
 mod info;
 mod init;
 mod link;
+mod manifest;
+mod package;
+mod serve;
+mod size;
 mod watch;
 
-pub use self::build::Build;
+pub use self::build::{Build, BuildCancel, BuildResult, BuildStatus};
 pub use self::clean::Clean;
 pub use self::cli::Cli;
 pub use self::create::Create;
+pub use self::daemon::Daemon;
+pub use self::dashboard::Dashboard;
+pub use self::deps::DepGraph;
+pub use self::diagnostic::{strip_ansi, Diagnostic};
 pub use self::error::RswErr;
+pub use self::event::Event;
+pub use self::fingerprint::Fingerprint;
+pub use self::hook::{HookStage, Hooks};
 pub use self::info::RswInfo;
 pub use self::init::Init;
 pub use self::link::Link;
-pub use self::watch::Watch;
+pub use self::manifest::{Manifest, ManifestCrate, ManifestFile, ManifestOutput};
+pub use self::package::Package;
+pub use self::serve::{LiveReload, Serve};
+pub use self::size::{format_delta, format_size, parse_size, FileSize, SizeReport};
+pub use self::watch::{SharedConfig, Watch, WatchCallback, WatchRequest};
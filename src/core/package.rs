@@ -0,0 +1,200 @@
+//! rsw package
+//!
+//! Combine the outputs of several targets into one npm package
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::PathBuf;
+
+/// A target output inside the combined package
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageEntry {
+    /// wasm-pack target: `web` | `bundler` | `nodejs` ...
+    pub target: String,
+    /// directory, relative to the combined package
+    pub dir: String,
+    /// the js entry generated by wasm-pack
+    pub js: String,
+    /// the `*.d.ts` generated by wasm-pack
+    pub types: Option<String>,
+}
+
+pub struct Package {
+    out_dir: PathBuf,
+    outputs: Vec<(String, String)>,
+}
+
+impl Package {
+    /// `out_dir` - the combined package,
+    /// `outputs` - targets and their directories relative to `out_dir`
+    pub fn new(out_dir: PathBuf, outputs: Vec<(String, String)>) -> Package {
+        Package { out_dir, outputs }
+    }
+
+    /// Write `<out-dir>/package.json` with an `exports` map covering all targets
+    pub fn init(&self) -> Result<()> {
+        let mut base: Option<Value> = None;
+        let mut entries = Vec::new();
+        let mut side_effects = Vec::new();
+
+        for (target, dir) in &self.outputs {
+            let target_dir = self.out_dir.join(dir);
+            let pkg_file = target_dir.join("package.json");
+            let pkg: Value = fs::read_to_string(&pkg_file)
+                .map_err(|e| anyhow!("{}: {}", pkg_file.display(), e))
+                .and_then(|content| serde_json::from_str(&content).map_err(Into::into))?;
+
+            let js = pkg["module"]
+                .as_str()
+                .or_else(|| pkg["main"].as_str())
+                .ok_or_else(|| anyhow!("{}: no `module` or `main`", pkg_file.display()))?;
+            entries.push(PackageEntry {
+                target: target.into(),
+                dir: dir.into(),
+                js: js.into(),
+                types: pkg["types"].as_str().map(Into::into),
+            });
+            if let Some(effects) = pkg["sideEffects"].as_array() {
+                for effect in effects.iter().filter_map(|e| e.as_str()) {
+                    side_effects.push(format!("./{}/{}", dir, effect.trim_start_matches("./")));
+                }
+            }
+
+            // wasm-pack ignores everything in its output directory
+            let _ = fs::remove_file(target_dir.join(".gitignore"));
+
+            if base.is_none() {
+                base = Some(pkg);
+                for file in ["README.md", "LICENSE", "LICENSE-MIT", "LICENSE-APACHE"] {
+                    if target_dir.join(file).is_file() {
+                        fs::copy(target_dir.join(file), self.out_dir.join(file))?;
+                    }
+                }
+            }
+        }
+
+        let mut pkg = match base {
+            Some(Value::Object(pkg)) => pkg,
+            _ => return Err(anyhow!("no target to combine")),
+        };
+        for key in ["type", "files", "main", "module", "types", "sideEffects"] {
+            pkg.remove(key);
+        }
+
+        let dirs: Vec<&str> = entries.iter().map(|e| e.dir.as_str()).collect();
+        pkg.insert("files".into(), json!(dirs));
+        let find = |target: &str| entries.iter().find(|e| e.target == target);
+        if let Some(main) = find("nodejs").or_else(|| entries.first()) {
+            pkg.insert("main".into(), json!(entry_path(main, &main.js)));
+        }
+        if let Some(module) = find("bundler").or_else(|| find("web")) {
+            pkg.insert("module".into(), json!(entry_path(module, &module.js)));
+        }
+        if let Some(entry) = entries.iter().find(|e| e.types.is_some()) {
+            let types = entry.types.as_ref().unwrap();
+            pkg.insert("types".into(), json!(entry_path(entry, types)));
+        }
+        if !side_effects.is_empty() {
+            pkg.insert("sideEffects".into(), json!(side_effects));
+        }
+        pkg.insert("exports".into(), exports(&entries));
+
+        let content = serde_json::to_string_pretty(&Value::Object(pkg))?;
+        fs::write(self.out_dir.join("package.json"), content)?;
+
+        Ok(())
+    }
+}
+
+fn entry_path(entry: &PackageEntry, file: &str) -> String {
+    format!("./{}/{}", entry.dir, file)
+}
+
+/// The `exports` field, conditions are matched in order:
+///
+/// - `types` - typescript
+/// - `node` - Node.js `import` and `require`, the `nodejs` target
+/// - `browser` - bundlers building for browsers, the `bundler` or `web` target
+/// - `import` - other ESM environments, the `web` or `bundler` target
+/// - `require` - CommonJS, the `nodejs` target
+/// - `default`
+pub fn exports(entries: &[PackageEntry]) -> Value {
+    let find = |target: &str| entries.iter().find(|e| e.target == target);
+    let node = find("nodejs");
+    let browser = find("bundler").or_else(|| find("web"));
+    let import = find("web").or_else(|| find("bundler"));
+    let default = import.or(node).or_else(|| entries.first());
+
+    let mut root = Map::new();
+    if let Some(entry) = entries.iter().find(|e| e.types.is_some()) {
+        let types = entry.types.as_ref().unwrap();
+        root.insert("types".into(), json!(entry_path(entry, types)));
+    }
+    let conditions = [
+        ("node", node),
+        ("browser", browser),
+        ("import", import),
+        ("require", node),
+        ("default", default),
+    ];
+    for (condition, entry) in conditions {
+        if let Some(entry) = entry {
+            root.insert(condition.into(), json!(entry_path(entry, &entry.js)));
+        }
+    }
+
+    let mut exports = Map::new();
+    exports.insert(".".into(), Value::Object(root));
+    for entry in entries {
+        exports.insert(
+            format!("./{}", entry.dir),
+            json!(entry_path(entry, &entry.js)),
+        );
+        exports.insert(
+            format!("./{}/*", entry.dir),
+            json!(format!("./{}/*", entry.dir)),
+        );
+    }
+    exports.insert("./package.json".into(), json!("./package.json"));
+
+    Value::Object(exports)
+}
+
+#[cfg(test)]
+mod package_tests {
+    use super::*;
+
+    fn entry(target: &str, dir: &str) -> PackageEntry {
+        PackageEntry {
+            target: target.into(),
+            dir: dir.into(),
+            js: "foo.js".into(),
+            types: Some("foo.d.ts".into()),
+        }
+    }
+
+    #[test]
+    fn exports_all_targets() {
+        let entries = vec![
+            entry("web", "web"),
+            entry("nodejs", "node"),
+            entry("bundler", "bundler"),
+        ];
+        let exports = exports(&entries);
+        assert_eq!(
+            serde_json::to_string(&exports["."]).unwrap(),
+            r#"{"types":"./web/foo.d.ts","node":"./node/foo.js","browser":"./bundler/foo.js","import":"./web/foo.js","require":"./node/foo.js","default":"./web/foo.js"}"#
+        );
+        assert_eq!(exports["./node"], json!("./node/foo.js"));
+        assert_eq!(exports["./web/*"], json!("./web/*"));
+    }
+
+    #[test]
+    fn exports_web_and_nodejs() {
+        let entries = vec![entry("web", "web"), entry("nodejs", "node")];
+        let exports = exports(&entries);
+        assert_eq!(exports["."]["browser"], json!("./web/foo.js"));
+        assert_eq!(exports["."]["require"], json!("./node/foo.js"));
+    }
+}
@@ -0,0 +1,343 @@
+//! rsw serve
+//!
+//! A local static server for the project root and the crates' `out-dir`,
+//! pages reload after every successful build of `rsw watch`.
+
+use path_clean::PathClean;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::core::deps::crate_root;
+use crate::core::{RswErr, RswInfo, SharedConfig};
+use crate::utils::{get_root, print};
+
+// Server-Sent Events endpoint of the reload script
+static RELOAD_PATH: &str = "/__rsw/reload";
+
+// `SharedArrayBuffer` is only available to cross-origin isolated pages
+static ISOLATION_HEADERS: &str =
+    "Cross-Origin-Opener-Policy: same-origin\r\nCross-Origin-Embedder-Policy: require-corp\r\n";
+
+// a page that stops reading is dropped after this long, not to hold up the builds
+static RELOAD_TIMEOUT: Duration = Duration::from_millis(500);
+
+static RELOAD_SCRIPT: &str = r#"<script>
+new EventSource("/__rsw/reload").addEventListener("reload", () => location.reload());
+</script>"#;
+
+/// The pages connected to the reload endpoint
+#[derive(Clone, Default)]
+pub struct LiveReload(Arc<Mutex<Vec<TcpStream>>>);
+
+impl LiveReload {
+    /// Tell every connected page to reload, closed pages are dropped
+    pub fn reload(&self, name: &str) {
+        let message = format!("event: reload\ndata: {}\n\n", name);
+        let mut clients = self.0.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(message.as_bytes()).is_ok());
+    }
+
+    fn connect(&self, mut stream: TcpStream) {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+        let _ = stream.set_write_timeout(Some(RELOAD_TIMEOUT));
+        if stream.write_all(head.as_bytes()).is_ok() {
+            self.0.lock().unwrap().push(stream);
+        }
+    }
+}
+
+pub struct Serve {
+    addr: String,
+    root: PathBuf,
+    /// the crates of the running watch, reloaded with `rsw.toml`
+    config: SharedConfig,
+    reload: LiveReload,
+}
+
+impl Serve {
+    pub fn new(config: SharedConfig, host: &str, port: u16, reload: LiveReload) -> Serve {
+        Serve {
+            addr: format!("{}:{}", host, port),
+            root: get_root(),
+            config,
+            reload,
+        }
+    }
+
+    // send the cross-origin isolation headers, for crates with `threads = true`
+    fn is_isolated(&self) -> bool {
+        self.config.get().crates.iter().any(|i| i.is_threads())
+    }
+
+    // `/<name>` -> the `out-dir` of the crate
+    fn mounts(&self) -> Vec<(String, PathBuf)> {
+        self.config
+            .get()
+            .crates
+            .iter()
+            .map(|i| (format!("/{}", i.name), crate_root(i).join(i.link_dir())))
+            .collect()
+    }
+
+    /// Listen in the background
+    pub fn init(self) {
+        let listener = TcpListener::bind(&self.addr).unwrap_or_else(|e| {
+            print(RswErr::Serve(self.addr.clone(), e));
+            std::process::exit(1);
+        });
+        print(RswInfo::Serve(format!("http://{}", self.addr)));
+
+        let serve = Arc::new(self);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let serve = serve.clone();
+                std::thread::spawn(move || serve.handle(stream));
+            }
+        });
+    }
+
+    fn handle(&self, mut stream: TcpStream) {
+        let mut request_line = String::new();
+        let mut reader = match stream.try_clone() {
+            Ok(s) => BufReader::new(s),
+            Err(_) => return,
+        };
+        if reader.read_line(&mut request_line).is_err() {
+            return;
+        }
+        // skip the headers
+        let mut line = String::new();
+        while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+            line.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or("/");
+        let url_path = target.split(['?', '#']).next().unwrap_or("/");
+        debug!("{} {}", method, url_path);
+
+        if method != "GET" && method != "HEAD" {
+            let _ = self.respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                b"",
+                true,
+            );
+            return;
+        }
+        if url_path == RELOAD_PATH {
+            self.reload.connect(stream);
+            return;
+        }
+
+        let file = match self.resolve(&percent_decode(url_path)) {
+            Some(file) => file,
+            None => {
+                let _ = self.respond(
+                    &mut stream,
+                    "404 Not Found",
+                    "text/plain",
+                    b"Not Found",
+                    true,
+                );
+                return;
+            }
+        };
+        let mut body = match fs::read(&file) {
+            Ok(body) => body,
+            Err(_) => {
+                let _ = self.respond(
+                    &mut stream,
+                    "404 Not Found",
+                    "text/plain",
+                    b"Not Found",
+                    true,
+                );
+                return;
+            }
+        };
+        let content_type = content_type(&file);
+        if content_type.starts_with("text/html") {
+            body = inject_reload(&body);
+        }
+        let _ = self.respond(&mut stream, "200 OK", content_type, &body, method == "GET");
+    }
+
+    fn respond(
+        &self,
+        stream: &mut TcpStream,
+        status: &str,
+        content_type: &str,
+        body: &[u8],
+        with_body: bool,
+    ) -> std::io::Result<()> {
+        let isolation = match self.is_isolated() {
+            true => ISOLATION_HEADERS,
+            false => "",
+        };
+        let head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n{}Connection: close\r\n\r\n",
+            status,
+            content_type,
+            body.len(),
+            isolation,
+        );
+        stream.write_all(head.as_bytes())?;
+        if with_body {
+            stream.write_all(body)?;
+        }
+        stream.flush()
+    }
+
+    // the file of an url path, `/<name>/...` is looked up in the crate's `out-dir` first,
+    // then everything in the project root, `index.html` for directories
+    fn resolve(&self, url_path: &str) -> Option<PathBuf> {
+        let mounts = self.mounts();
+        let mut mounts: Vec<_> = mounts
+            .iter()
+            .filter(|(prefix, _)| {
+                url_path == prefix || url_path.starts_with(&format!("{}/", prefix))
+            })
+            .map(|(prefix, dir)| (dir.as_path(), &url_path[prefix.len()..]))
+            .collect();
+        mounts.sort_by_key(|(_, rest)| rest.len());
+        mounts.push((self.root.as_path(), url_path));
+
+        mounts.into_iter().find_map(|(base, rest)| {
+            let mut file = safe_join(base, rest)?;
+            if file.is_dir() {
+                file = file.join("index.html");
+            }
+            file.is_file().then_some(file)
+        })
+    }
+}
+
+// `base` + `url_path`, `None` if it leaves `base`
+fn safe_join(base: &Path, url_path: &str) -> Option<PathBuf> {
+    let path = base.join(url_path.trim_start_matches('/')).clean();
+    path.starts_with(base).then_some(path)
+}
+
+/// The MIME type of a file, `.wasm` is `application/wasm`
+/// so that `WebAssembly.instantiateStreaming` accepts it.
+pub fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "js" | "mjs" | "cjs" => "text/javascript; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "json" | "map" => "application/json",
+        "wasm" => "application/wasm",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "txt" | "md" | "ts" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+// the reload script, before `</body>` or at the end of the page
+fn inject_reload(html: &[u8]) -> Vec<u8> {
+    let html = String::from_utf8_lossy(html);
+    match html.rfind("</body>") {
+        Some(idx) => format!("{}{}{}", &html[..idx], RELOAD_SCRIPT, &html[idx..]),
+        None => format!("{}{}", html, RELOAD_SCRIPT),
+    }
+    .into_bytes()
+}
+
+// `%20` -> ` `
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = bytes
+            .get(idx + 1..idx + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[idx], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod serve_tests {
+    use super::*;
+
+    #[test]
+    fn wasm_content_type() {
+        assert_eq!(
+            content_type(Path::new("pkg/foo_bg.wasm")),
+            "application/wasm"
+        );
+        assert_eq!(
+            content_type(Path::new("index.HTML")),
+            "text/html; charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn drop_stalled_page() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        // never reads
+        let _page = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let reload = LiveReload::default();
+        reload.connect(listener.accept().unwrap().0);
+
+        let name = "x".repeat(1 << 16);
+        for _ in 0..1000 {
+            if reload.0.lock().unwrap().is_empty() {
+                break;
+            }
+            reload.reload(&name);
+        }
+        assert!(reload.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn join_inside_base() {
+        let base = Path::new("/www");
+        assert_eq!(
+            safe_join(base, "/a/b.js"),
+            Some(PathBuf::from("/www/a/b.js"))
+        );
+        assert_eq!(safe_join(base, "/../etc/passwd"), None);
+        assert_eq!(safe_join(base, "/a/../../etc"), None);
+    }
+
+    #[test]
+    fn inject_before_body() {
+        let html = inject_reload(b"<html><body><p>hi</p></body></html>");
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.ends_with(&format!("{}</body></html>", RELOAD_SCRIPT)));
+    }
+
+    #[test]
+    fn decode_percent() {
+        assert_eq!(percent_decode("/a%20b/%40rsw"), "/a b/@rsw");
+        assert_eq!(percent_decode("/100%"), "/100%");
+    }
+}
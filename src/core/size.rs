@@ -0,0 +1,155 @@
+//! rsw size
+//!
+//! Raw and gzip sizes of the generated `.wasm` and js glue, with a history in `.rsw/size/`
+
+use anyhow::Result;
+use flate2::{write::GzEncoder, Compression};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config;
+use crate::utils::dot_rsw_dir;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSize {
+    pub raw: u64,
+    pub gzip: u64,
+}
+
+/// A line of `.rsw/size/<name>.jsonl`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeReport {
+    /// unix timestamp, in seconds
+    pub time: u64,
+    pub wasm: FileSize,
+    pub js: FileSize,
+}
+
+impl SizeReport {
+    /// With several targets, the output with the largest `.wasm` is reported.
+    pub fn new(out_dirs: &[PathBuf]) -> SizeReport {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut report = SizeReport {
+            time,
+            ..Default::default()
+        };
+
+        for out_dir in out_dirs {
+            let wasm = dir_size(out_dir, "wasm");
+            if wasm.raw >= report.wasm.raw {
+                report.wasm = wasm;
+                report.js = dir_size(out_dir, "js");
+            }
+        }
+
+        report
+    }
+
+    /// The report of the previous build
+    pub fn last(name: &str) -> Option<SizeReport> {
+        let content = fs::read_to_string(history_path(name)).ok()?;
+        let line = content.lines().rev().find(|l| !l.trim().is_empty())?;
+        serde_json::from_str(line).ok()
+    }
+
+    pub fn append(&self, name: &str) -> Result<()> {
+        let path = history_path(name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+// `.rsw/size/<name>.jsonl`, the scope separator of `@rsw/foo` is replaced
+fn history_path(name: &str) -> PathBuf {
+    dot_rsw_dir()
+        .join(config::RSW_SIZE)
+        .join(format!("{}.jsonl", name.replace('/', "__")))
+}
+
+// total size of the files with the extension in the directory
+fn dir_size(dir: &Path, extension: &str) -> FileSize {
+    let mut size = FileSize::default();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return size,
+    };
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.extension().is_some_and(|ext| ext == extension) {
+            if let Ok(content) = fs::read(&path) {
+                size.raw += content.len() as u64;
+                size.gzip += gzip_size(&content);
+            }
+        }
+    }
+    size
+}
+
+fn gzip_size(content: &[u8]) -> u64 {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let _ = encoder.write_all(content);
+    encoder.finish().map(|c| c.len() as u64).unwrap_or_default()
+}
+
+/// `1536` -> `1.50 KB`
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1024 * 1024 => format!("{:.2} MB", b as f64 / (1024.0 * 1024.0)),
+        b if b >= 1024 => format!("{:.2} KB", b as f64 / 1024.0),
+        b => format!("{} B", b),
+    }
+}
+
+/// `+1.50 KB` | `-12 B`
+pub fn format_delta(now: u64, prev: u64) -> String {
+    match now >= prev {
+        true => format!("+{}", format_size(now - prev)),
+        false => format!("-{}", format_size(prev - now)),
+    }
+}
+
+/// `200KB` | `1.5 MB` | `1024` -> bytes
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim().to_uppercase();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (num, unit) = size.split_at(split);
+    let num: f64 = num.parse().ok()?;
+    let unit = match unit.trim() {
+        "" | "B" => 1.0,
+        "K" | "KB" | "KIB" => 1024.0,
+        "M" | "MB" | "MIB" => 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((num * unit) as u64)
+}
+
+#[cfg(test)]
+mod size_tests {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("1024"), Some(1024));
+        assert_eq!(parse_size("200KB"), Some(200 * 1024));
+        assert_eq!(parse_size("1.5 mb"), Some(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_size("10 apples"), None);
+    }
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(12), "12 B");
+        assert_eq!(format_size(1536), "1.50 KB");
+        assert_eq!(format_delta(1000, 1012), "-12 B");
+        assert_eq!(format_delta(2048, 1024), "+1.00 KB");
+    }
+}
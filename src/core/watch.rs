@@ -1,40 +1,117 @@
 //! rsw watch
 
-use ignore::gitignore::Gitignore;
-use notify::{DebouncedEvent::*, RecursiveMode::*, Watcher};
-use path_clean::PathClean;
+use globset::{GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::{
+    DebouncedEvent, DebouncedEvent::*, PollWatcher, RecommendedWatcher, RecursiveMode,
+    RecursiveMode::*, Watcher,
+};
 use std::{
-    collections::HashMap, path::PathBuf, sync::mpsc::channel, sync::Arc, thread::sleep,
-    time::Duration,
+    collections::{HashMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    sync::{Arc, Condvar, Mutex, RwLock},
+    thread::sleep,
+    time::{Duration, Instant},
 };
 
-use crate::config::{CrateConfig, RswConfig};
-use crate::core::{Build, RswErr, RswInfo};
+use crate::config::{CrateConfig, RswConfig, WatchStrategy, RSW_FILE};
+use crate::core::deps::{crate_root, input_paths, watch_glob};
+use crate::core::{Build, BuildCancel, Cli, Event, RswErr, RswInfo};
 
 use crate::utils::{get_root, print};
 
+/// called after a crate is successfully rebuilt in `watch` mode
+pub type WatchCallback = Arc<dyn Fn(&CrateConfig, PathBuf) + Send + Sync + 'static>;
+
+/// Requests to a running watch, e.g. from `rsw daemon` or the dashboard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchRequest {
+    /// rebuild the crates, every watched crate if empty
+    Build(Vec<String>),
+    /// switch the watch `profile` of a crate and rebuild it, until `rsw.toml` is reloaded
+    Profile(String, String),
+    /// stop or resume building on file changes, the crates changed meanwhile are built on resume
+    Pause(bool),
+    /// cancel the running builds and exit
+    Quit,
+}
+
+// file events and requests, handled in batches by the watch loop
+enum WatchMessage {
+    File(DebouncedEvent),
+    Request(WatchRequest),
+}
+
+/// The config of a running watch, replaced when `rsw.toml` is reloaded
+#[derive(Clone)]
+pub struct SharedConfig(Arc<RwLock<Arc<RswConfig>>>);
+
+impl SharedConfig {
+    pub fn new(config: Arc<RswConfig>) -> SharedConfig {
+        SharedConfig(Arc::new(RwLock::new(config)))
+    }
+
+    pub fn get(&self) -> Arc<RswConfig> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, config: Arc<RswConfig>) {
+        *self.0.write().unwrap() = config;
+    }
+}
+
 pub struct Watch {
-    config: Arc<RswConfig>,
-    callback: Arc<dyn Fn(&CrateConfig, PathBuf) + Send + Sync + 'static>,
+    config: SharedConfig,
+    callback: WatchCallback,
+    requests: Vec<Receiver<WatchRequest>>,
 }
 
 impl Watch {
-    pub fn new(
-        config: Arc<RswConfig>,
-        callback: Arc<dyn Fn(&CrateConfig, PathBuf) + Send + Sync + 'static>,
-    ) -> Watch {
-        Watch { config, callback }
+    pub fn new(config: Arc<RswConfig>, callback: WatchCallback) -> Watch {
+        Watch {
+            config: SharedConfig::new(config),
+            callback,
+            requests: Vec::new(),
+        }
     }
+
+    /// the current config, e.g. for the crates served by `rsw serve`
+    pub fn config(&self) -> SharedConfig {
+        self.config.clone()
+    }
+
+    /// handle the requests sent to `rx` along with the file changes
+    pub fn requests(mut self, rx: Receiver<WatchRequest>) -> Watch {
+        self.requests.push(rx);
+        self
+    }
+
     pub fn init(self) {
-        let config = self.config;
+        let shared = self.config;
+        let mut config = shared.get();
         let caller = self.callback;
-        let mut crate_map = HashMap::new();
-        let mut path_map = HashMap::new();
-        let (tx, rx) = channel();
+        let (messages, rx) = channel();
+        let (tx, file_rx) = channel();
+        forward(file_rx, messages.clone(), WatchMessage::File);
+        for requests in self.requests {
+            forward(requests, messages.clone(), WatchMessage::Request);
+        }
         // Keep the root as a path instead
         let cwd = get_root();
+        let rsw_file = cwd.join(RSW_FILE);
+        let watchignore_file = cwd.join(".watchignore");
 
-        let mut watcher = match notify::watcher(tx, Duration::from_secs(1)) {
+        let watcher_options = config.watch.as_ref().unwrap();
+        let watcher = match watcher_options.poll.unwrap_or(false) {
+            true => {
+                let interval = watcher_options.poll_interval.unwrap_or(1000);
+                PollWatcher::new(tx, Duration::from_millis(interval)).map(FileWatcher::Poll)
+            }
+            false => notify::watcher(tx, Duration::from_secs(1)).map(FileWatcher::Native),
+        };
+        let mut watcher = match watcher {
             Ok(w) => w,
             Err(e) => {
                 print(RswErr::WatchFile(e));
@@ -42,46 +119,64 @@ impl Watch {
             }
         };
 
-        for i in &config.crates {
-            // TODO: local deps watch
-
-            // fix: https://github.com/rwasm/rsw-rs/issues/5#issuecomment-1242822143
-            // Make sure the crate root path is absolute
-            let crate_root = {
-                let cr = PathBuf::from(i.root.as_deref().unwrap()).join(&i.name);
-                if cr.is_relative() {
-                    cwd.to_owned().join(cr)
-                } else {
-                    cwd.to_owned()
-                }
-            };
-
-            let _ = watcher.watch(crate_root.join("src"), Recursive);
-            let _ = watcher.watch(crate_root.join("Cargo.toml"), NonRecursive);
-
-            crate_map.insert(&i.name, i);
-            path_map.insert(&i.name, crate_root.clean());
+        // `rsw.toml` and `.watchignore`, watched through the project root to see them created
+        let _ = watcher.watch(&cwd, NonRecursive);
+        let mut watched = Vec::new();
+        let mut path_map = watch_crates(&config, &mut watcher, &mut watched);
 
+        let mut crates = Vec::new();
+        for i in &config.crates {
             if i.watch.as_ref().unwrap().run.unwrap() {
                 print(RswInfo::RunWatch(i.name.clone()));
+                crates.push(i.name.clone());
             }
         }
-
         print(RswInfo::SplitLine);
+        Event::WatchStarted { crates }.emit();
 
-        let (gitignore, _) = Gitignore::new("./.watchignore");
-        let mut build_task_join_handle: Option<tokio::task::JoinHandle<()>> = None;
+        let (mut gitignore, _) = Gitignore::new(&watchignore_file);
+        // one build queue per crate, at most `jobs` builds running at the same time
+        let slots = Arc::new(JobSlots::new(config.jobs.unwrap_or(1)));
+        let mut queues: HashMap<String, Arc<Mutex<CrateQueue>>> = HashMap::new();
+        let mut paused = false;
+        // changed while paused
+        let mut deferred: Vec<(String, PathBuf)> = Vec::new();
 
         loop {
             let first_event = rx.recv().unwrap();
             sleep(Duration::from_millis(config.interval.unwrap()));
             let other_events = rx.try_iter();
 
+            // the crates changed by this batch of events, with the last changed path
+            let mut changed: Vec<(String, PathBuf)> = Vec::new();
+            let mut is_config_changed = false;
+            let mut is_ignore_changed = false;
+            let mut requests: Vec<WatchRequest> = Vec::new();
+
             let all_events = std::iter::once(first_event).chain(other_events);
-            for event in all_events {
+            for message in all_events {
+                let event = match message {
+                    WatchMessage::File(event) => event,
+                    WatchMessage::Request(request) => {
+                        requests.push(request);
+                        continue;
+                    }
+                };
                 debug!("{:?}", event);
 
                 match event {
+                    // the polling watcher also reports directories whose entries changed
+                    Write(path) if path.is_dir() => (),
+                    Create(path) | Write(path) | Remove(path) | Rename(_, path)
+                        if path == rsw_file =>
+                    {
+                        is_config_changed = true;
+                    }
+                    Create(path) | Write(path) | Remove(path) | Rename(_, path)
+                        if path == watchignore_file =>
+                    {
+                        is_ignore_changed = true;
+                    }
                     Write(path) | Remove(path) | Rename(_, path) => {
                         // Simplify gitignore matching code
                         // strip_prefix is simpler to use
@@ -94,46 +189,526 @@ impl Watch {
                             continue;
                         }
 
+                        // every crate that uses the changed file
+                        let mut crates = Vec::new();
                         for (key, val) in &path_map {
-                            // Use starts_with instead of regex comparing strings
-                            // This way we avoid potential issues with extra slashes
-                            if !path.starts_with(val) {
+                            if !val.matches(&path) {
                                 continue;
                             }
 
-                            let crate_config = (*crate_map.get(key).unwrap()).clone();
+                            crates.push(key.to_string());
+                            changed.retain(|(name, _)| name != key);
+                            changed.push((key.to_string(), path.clone()));
+                        }
+                        if !crates.is_empty() {
                             print(RswInfo::CrateChange(path.clone().to_path_buf()));
+                            crates.sort();
+                            Event::FileChanged { path, crates }.emit();
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            if is_ignore_changed {
+                gitignore = Gitignore::new(&watchignore_file).0;
+                print(RswInfo::ConfigReload(".watchignore".into()));
+            }
+
+            // keep the previous config when the new one is invalid
+            if is_config_changed {
+                match reload_config(&config) {
+                    Ok(new_config) => {
+                        Cli::init_crates(&new_config);
+                        path_map = watch_crates(&new_config, &mut watcher, &mut watched);
 
-                            if let Some(join_handle) = build_task_join_handle {
-                                debug!("abort building task");
-                                join_handle.abort();
+                        // rebuild the added crates and those whose options changed
+                        for i in &new_config.crates {
+                            let prev = config.crates.iter().find(|c| c.name == i.name);
+                            if !prev.is_some_and(|prev| same_crate(prev, i)) {
+                                changed.retain(|(name, _)| *name != i.name);
+                                changed.push((i.name.clone(), rsw_file.clone()));
                             }
+                        }
+
+                        config = Arc::new(new_config);
+                        shared.set(config.clone());
+                        print(RswInfo::ConfigReload(RSW_FILE.into()));
+                    }
+                    Err(e) => print(e),
+                }
+            }
 
-                            let config = config.clone();
-                            let caller = caller.clone();
-                            let path = path.clone();
-                            let join_handle = tokio::spawn(async move {
-                                let is_ok = Build::new(
-                                    crate_config.clone(),
-                                    "watch",
-                                    config.cli.to_owned().unwrap(),
-                                    false,
-                                )
-                                .init();
-
-                                if is_ok {
-                                    caller(&crate_config, path.clone());
-                                }
-                            });
-
-                            build_task_join_handle = Some(join_handle);
-
-                            break;
+            for request in &requests {
+                if let WatchRequest::Pause(pause) = request {
+                    paused = *pause;
+                }
+            }
+            // the crates changed while paused are built on resume
+            let (from, to) = match paused {
+                true => (&mut changed, &mut deferred),
+                false => (&mut deferred, &mut changed),
+            };
+            for (name, path) in std::mem::take(from) {
+                to.retain(|(i, _)| *i != name);
+                to.push((name, path));
+            }
+
+            // requested builds, of the crates in the current config
+            for request in requests {
+                match request {
+                    WatchRequest::Build(names) => {
+                        for i in &config.crates {
+                            let is_requested = match names.is_empty() {
+                                true => i.watch.as_ref().unwrap().run.unwrap(),
+                                false => names.contains(&i.name),
+                            };
+                            if is_requested {
+                                changed.retain(|(name, _)| *name != i.name);
+                                changed.push((i.name.clone(), crate_root(i)));
+                            }
                         }
                     }
-                    _ => (),
+                    WatchRequest::Profile(name, profile) => {
+                        let mut new_config = (*config).clone();
+                        if let Some(i) = new_config.crates.iter_mut().find(|i| i.name == name) {
+                            i.watch.as_mut().unwrap().profile = Some(profile);
+                            changed.retain(|(i, _)| *i != name);
+                            changed.push((name, crate_root(i)));
+                        }
+                        config = Arc::new(new_config);
+                        shared.set(config.clone());
+                    }
+                    WatchRequest::Pause(_) => (),
+                    WatchRequest::Quit => quit(&queues),
+                }
+            }
+
+            for (name, path) in changed {
+                let crate_config = match config.crates.iter().find(|i| i.name == name) {
+                    Some(i) => i.clone(),
+                    None => continue,
+                };
+                let strategy = crate_config.watch.as_ref().unwrap().strategy;
+                let queue = queues.entry(name).or_default().clone();
+                let mut state = queue.lock().unwrap();
+
+                state.push(
+                    strategy.unwrap_or_default(),
+                    QueuedBuild {
+                        path,
+                        crate_config,
+                        config: config.clone(),
+                    },
+                );
+
+                if !state.running {
+                    state.running = true;
+                    let queue = queue.clone();
+                    let caller = caller.clone();
+                    let slots = slots.clone();
+                    std::thread::spawn(move || run_queue(queue, caller, slots));
+                }
+            }
+        }
+    }
+}
+
+// cancel the running builds, wait for their process trees to be killed and exit
+fn quit(queues: &HashMap<String, Arc<Mutex<CrateQueue>>>) -> ! {
+    for queue in queues.values() {
+        let mut state = queue.lock().unwrap();
+        state.pending.clear();
+        state.cancel.cancel();
+    }
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline && queues.values().any(|q| q.lock().unwrap().running) {
+        sleep(Duration::from_millis(50));
+    }
+    std::process::exit(0);
+}
+
+// pass the messages of `rx` on to the watch loop
+fn forward<T: Send + 'static>(
+    rx: Receiver<T>,
+    tx: Sender<WatchMessage>,
+    wrap: fn(T) -> WatchMessage,
+) {
+    std::thread::spawn(move || {
+        for message in rx {
+            if tx.send(wrap(message)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+// `rsw.toml` changed: parse it and check the crates,
+// `jobs` and `[watch]` only apply when `rsw watch` starts
+fn reload_config(prev: &RswConfig) -> Result<RswConfig, RswErr> {
+    let mut config = RswConfig::load()?;
+    for i in &config.crates {
+        let manifest = crate_root(i).join("Cargo.toml");
+        if let Err(e) = fs::metadata(&manifest) {
+            return Err(RswErr::Crate(i.name.clone(), e));
+        }
+    }
+    config.jobs = prev.jobs;
+    config.watch = prev.watch.clone();
+
+    Ok(config)
+}
+
+fn same_crate(a: &CrateConfig, b: &CrateConfig) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// watch the files of every crate, and stop watching those no crate uses anymore
+fn watch_crates(
+    config: &RswConfig,
+    watcher: &mut FileWatcher,
+    watched: &mut Vec<PathBuf>,
+) -> HashMap<String, CrateWatch> {
+    let mut path_map = HashMap::new();
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for i in &config.crates {
+        let crate_watch = CrateWatch::new(i);
+        for path in crate_watch.watch_paths() {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+        path_map.insert(i.name.clone(), crate_watch);
+    }
+
+    for path in watched.iter().filter(|p| !paths.contains(p)) {
+        let _ = watcher.unwatch(path);
+    }
+    for path in paths.iter().filter(|p| !watched.contains(p)) {
+        let mode = if path.is_dir() {
+            Recursive
+        } else {
+            NonRecursive
+        };
+        let _ = watcher.watch(path, mode);
+    }
+    *watched = paths;
+
+    path_map
+}
+
+// native file system events, or polling
+enum FileWatcher {
+    Native(RecommendedWatcher),
+    Poll(PollWatcher),
+}
+
+impl FileWatcher {
+    fn watch(&mut self, path: &Path, mode: RecursiveMode) -> notify::Result<()> {
+        match self {
+            FileWatcher::Native(watcher) => watcher.watch(path, mode),
+            FileWatcher::Poll(watcher) => watcher.watch(path, mode),
+        }
+    }
+
+    fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+        match self {
+            FileWatcher::Native(watcher) => watcher.unwatch(path),
+            FileWatcher::Poll(watcher) => watcher.unwatch(path),
+        }
+    }
+}
+
+// the files that trigger a rebuild of a crate
+struct CrateWatch {
+    root: PathBuf,
+    // the crate, its local path dependencies and its workspace files, as absolute paths
+    // (fix: https://github.com/rwasm/rsw-rs/issues/5#issuecomment-1242822143)
+    inputs: Vec<PathBuf>,
+    // `[crates.watch] paths`, the directories to watch and the globs to match
+    dirs: Vec<PathBuf>,
+    globs: GlobSet,
+    // `[crates.watch] ignore`
+    ignore: Gitignore,
+}
+
+impl CrateWatch {
+    fn new(config: &CrateConfig) -> CrateWatch {
+        let root = crate_root(config);
+        let watch = config.watch.as_ref().unwrap();
+
+        let mut dirs = Vec::new();
+        let mut globs = GlobSetBuilder::new();
+        for pattern in watch.paths.iter().flatten() {
+            match watch_glob(&root, pattern) {
+                Ok((glob, dir)) => {
+                    globs.add(glob);
+                    dirs.push(dir);
+                }
+                Err(e) => print(RswErr::WatchPattern(
+                    config.name.clone(),
+                    pattern.clone(),
+                    e.to_string(),
+                )),
+            }
+        }
+
+        let mut ignore = GitignoreBuilder::new(&root);
+        for pattern in watch.ignore.iter().flatten() {
+            if let Err(e) = ignore.add_line(None, pattern) {
+                print(RswErr::WatchPattern(
+                    config.name.clone(),
+                    pattern.clone(),
+                    e.to_string(),
+                ));
+            }
+        }
+
+        CrateWatch {
+            inputs: input_paths(&root),
+            dirs,
+            globs: globs.build().unwrap_or_else(|_| GlobSet::empty()),
+            ignore: ignore.build().unwrap_or_else(|_| Gitignore::empty()),
+            root,
+        }
+    }
+
+    fn watch_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.inputs
+            .iter()
+            .chain(self.dirs.iter())
+            .filter(|path| path.exists())
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        // Use starts_with instead of regex comparing strings
+        // This way we avoid potential issues with extra slashes
+        let is_input = self.inputs.iter().any(|p| path.starts_with(p)) || self.globs.is_match(path);
+        let is_ignored = path.starts_with(&self.root)
+            && self
+                .ignore
+                .matched_path_or_any_parents(path, path.is_dir())
+                .is_ignore();
+
+        is_input && !is_ignored
+    }
+}
+
+// the builds of a crate: the running one and those waiting for it
+#[derive(Default)]
+struct CrateQueue {
+    pending: VecDeque<QueuedBuild>,
+    running: bool,
+    cancel: BuildCancel,
+}
+
+impl CrateQueue {
+    // add the build of a change
+    fn push(&mut self, strategy: WatchStrategy, build: QueuedBuild) {
+        match strategy {
+            WatchStrategy::Abort => {
+                // kill the running wasm-pack, the next build starts once it exits
+                debug!("abort building task {}", build.crate_config.name);
+                self.cancel.cancel();
+                self.pending.clear();
+            }
+            // every change waits for its turn
+            WatchStrategy::Queue => (),
+            // the build reads the files when it starts, the last change stands for the others
+            WatchStrategy::Coalesce => self.pending.clear(),
+        }
+        self.pending.push_back(build);
+    }
+}
+
+// a change waiting for its build, with the config at the time of the change
+struct QueuedBuild {
+    path: PathBuf,
+    crate_config: CrateConfig,
+    config: Arc<RswConfig>,
+}
+
+// build the crate until its queue is empty
+fn run_queue(queue: Arc<Mutex<CrateQueue>>, caller: WatchCallback, slots: Arc<JobSlots>) {
+    loop {
+        let (queued, cancel) = {
+            let mut state = queue.lock().unwrap();
+            match state.pending.pop_front() {
+                Some(queued) => {
+                    state.cancel = BuildCancel::default();
+                    (queued, state.cancel.clone())
+                }
+                None => {
+                    state.running = false;
+                    return;
                 }
             }
+        };
+
+        let _slot = match slots.acquire(&cancel) {
+            Some(slot) => slot,
+            None => continue,
+        };
+        let QueuedBuild {
+            path,
+            crate_config,
+            config,
+        } = queued;
+        let is_ok = Build::new(
+            crate_config.clone(),
+            "watch",
+            config.cli.to_owned().unwrap(),
+            false,
+        )
+        .force(true)
+        .hooks(config.hooks.clone())
+        .cancel(cancel)
+        .init()
+        .is_ok();
+
+        if is_ok {
+            caller(&crate_config, path);
+        }
+    }
+}
+
+// a counting semaphore for the builds started by `watch`
+struct JobSlots {
+    free: Mutex<usize>,
+    freed: Condvar,
+}
+
+struct JobSlot(Arc<JobSlots>);
+
+impl JobSlots {
+    fn new(jobs: usize) -> JobSlots {
+        JobSlots {
+            free: Mutex::new(jobs.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    // wait for a free slot, `None` if the build is cancelled while waiting
+    fn acquire(self: &Arc<Self>, cancel: &BuildCancel) -> Option<JobSlot> {
+        let mut free = self.free.lock().unwrap();
+        while *free == 0 {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            free = self
+                .freed
+                .wait_timeout(free, Duration::from_millis(50))
+                .unwrap()
+                .0;
+        }
+        *free -= 1;
+        Some(JobSlot(self.clone()))
+    }
+}
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap() += 1;
+        self.0.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod crate_watch_tests {
+    use super::*;
+
+    #[test]
+    fn match_paths_and_ignore() {
+        let config: CrateConfig = toml::from_str(
+            r#"
+            name = "foo"
+            root = "/rsw_crate_watch_tests"
+            [watch]
+            paths = ["assets/*.txt", "../data/**"]
+            ignore = ["src/gen/"]
+            "#,
+        )
+        .unwrap();
+        let crate_watch = CrateWatch::new(&config);
+        let root = Path::new("/rsw_crate_watch_tests");
+
+        assert!(crate_watch.matches(&root.join("foo/src/lib.rs")));
+        assert!(crate_watch.matches(&root.join("foo/Cargo.toml")));
+        assert!(crate_watch.matches(&root.join("foo/assets/a.txt")));
+        assert!(crate_watch.matches(&root.join("data/a/b.bin")));
+        assert!(!crate_watch.matches(&root.join("foo/assets/sub/a.txt")));
+        assert!(!crate_watch.matches(&root.join("foo/src/gen/a.rs")));
+        assert!(!crate_watch.matches(&root.join("foo/README.md")));
+        assert_eq!(
+            crate_watch.dirs,
+            vec![root.join("foo/assets"), root.join("data")]
+        );
+    }
+}
+
+#[cfg(test)]
+mod crate_queue_tests {
+    use super::*;
+
+    fn push(queue: &mut CrateQueue, strategy: WatchStrategy, path: &str) {
+        queue.push(
+            strategy,
+            QueuedBuild {
+                path: PathBuf::from(path),
+                crate_config: toml::from_str("name = \"foo\"").unwrap(),
+                config: Arc::new(RswConfig::default()),
+            },
+        );
+    }
+
+    fn paths(queue: &CrateQueue) -> Vec<PathBuf> {
+        queue.pending.iter().map(|i| i.path.clone()).collect()
+    }
+
+    #[test]
+    fn queue_keeps_every_change() {
+        let mut queue = CrateQueue::default();
+        for path in ["a.rs", "b.rs", "c.rs"] {
+            push(&mut queue, WatchStrategy::Queue, path);
+        }
+        let expected: Vec<PathBuf> = ["a.rs", "b.rs", "c.rs"].iter().map(PathBuf::from).collect();
+        assert_eq!(paths(&queue), expected);
+        assert!(!queue.cancel.is_cancelled());
+    }
+
+    #[test]
+    fn one_build_waits() {
+        let mut queue = CrateQueue::default();
+        for path in ["a.rs", "b.rs", "c.rs"] {
+            push(&mut queue, WatchStrategy::Coalesce, path);
         }
+        assert_eq!(paths(&queue), vec![PathBuf::from("c.rs")]);
+        assert!(!queue.cancel.is_cancelled());
+
+        push(&mut queue, WatchStrategy::Abort, "d.rs");
+        assert_eq!(paths(&queue), vec![PathBuf::from("d.rs")]);
+        assert!(queue.cancel.is_cancelled());
+    }
+}
+
+#[cfg(test)]
+mod job_slots_tests {
+    use super::*;
+
+    #[test]
+    fn acquire_up_to_jobs() {
+        let slots = Arc::new(JobSlots::new(1));
+        let cancel = BuildCancel::default();
+        let slot = slots.acquire(&cancel);
+        assert!(slot.is_some());
+
+        cancel.cancel();
+        assert!(slots.acquire(&cancel).is_none());
+
+        drop(slot);
+        assert!(slots.acquire(&cancel).is_some());
     }
 }
@@ -33,6 +33,10 @@
 //! #! time interval for file changes to trigger wasm-pack build, default `50` milliseconds
 //! interval = 50
 //!
+//! #! the maximum number of crates to build in parallel, default is `1`
+//! #! `rsw build --jobs <N>` and `rsw watch --jobs <N>` override this value
+//! jobs = 1
+//!
 //! #! link
 //! #! npm link @see https://docs.npmjs.com/cli/v8/commands/npm-link
 //! #! yarn link @see https://classic.yarnpkg.com/en/docs/cli/link
@@ -117,5 +121,11 @@ pub fn rsw_cli() {
         std::process::exit(1);
     }
 
+    // the builds run in process groups of their own, out of reach of Ctrl-C
+    let _ = ctrlc::set_handler(|| {
+        core::BuildCancel::cancel_all(std::time::Duration::from_secs(5));
+        std::process::exit(130);
+    });
+
     Cli::init();
 }
@@ -5,13 +5,13 @@ use std::{
     fs::{self, File},
     io::{Read, Write},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Child, Command, Stdio},
 };
 use toml::Value;
 use which::which;
 
 use crate::config;
-use crate::core::RswErr;
+use crate::core::{Event, RswErr};
 
 pub fn check_env_cmd(program: &str) -> bool {
     let result = which(program);
@@ -171,8 +171,15 @@ pub fn init_logger() {
     builder.init();
 }
 
+// stdout is kept for `--message-format json` and `rsw daemon --stdio`
 pub fn print<T: std::fmt::Display>(a: T) {
-    println!("{}", a)
+    if Event::is_output_hidden() {
+        return;
+    }
+    match Event::is_stdout_reserved() {
+        true => eprintln!("{}", a),
+        false => println!("{}", a),
+    }
 }
 
 pub fn get_root() -> PathBuf {
@@ -219,22 +226,58 @@ pub fn rsw_watch_file(info_content: &[u8], err_content: &[u8], file_type: String
     Ok(())
 }
 
-pub fn os_cli<P: AsRef<Path>>(cli: String, args: Vec<String>, path: P) {
-    if cfg!(target_os = "windows") {
-        Command::new("cmd")
-            .arg("/C")
-            .arg(cli)
-            .args(args)
-            .current_dir(path)
-            .status()
-            .unwrap();
+/// `cli args` in `path`, through `cmd /C` on Windows
+pub fn os_command<P: AsRef<Path>>(cli: String, args: Vec<String>, path: P) -> Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(cli);
+        command
     } else {
         Command::new(cli)
-            .args(args)
-            .current_dir(path)
-            .status()
-            .unwrap();
+    };
+    command.args(args).current_dir(path);
+    // stdout is kept for `--message-format json` and `rsw daemon --stdio`
+    if Event::is_output_hidden() {
+        command.stdout(Stdio::null()).stderr(Stdio::null());
+    } else if Event::is_stdout_reserved() {
+        command.stdout(std::io::stderr());
+    }
+    command
+}
+
+pub fn os_cli<P: AsRef<Path>>(cli: String, args: Vec<String>, path: P) {
+    os_command(cli, args, path).status().unwrap();
+}
+
+/// Start the command in a process group of its own, see `kill_process_tree`
+pub fn new_process_group(command: &mut Command) -> &mut Command {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command
+}
+
+/// Kill a process started with `new_process_group` and all of its descendants
+pub fn kill_process_tree(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // the group id is the pid of its leader
+        if let Ok(pgid) = i32::try_from(child.id()) {
+            unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            }
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = Command::new("taskkill")
+            .args(["/T", "/F", "/PID", &child.id().to_string()])
+            .output();
     }
+    // the child itself, whatever happened to the group
+    let _ = child.kill();
 }
 
 // https://www.reddit.com/r/learnrust/comments/h82em8/best_way_to_create_a_vecstring_from_str/